@@ -1,5 +1,8 @@
 use std::collections::LinkedList;
 use std::fmt;
+
+use config::Config;
+use lexer::Lexer;
 use token;
 
 macro_rules! not_implemented {
@@ -9,12 +12,13 @@ macro_rules! not_implemented {
 }
 
 macro_rules! get_top {
-    ($t:ident) => {
-        $t.generate().pop_front().unwrap()
+    ($t:ident, $c:expr) => {
+        $t.generate_with($c).pop_front().unwrap()
     };
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::type_complexity)]
 pub enum ASTClass {
     Identifire(String),
     Number(String),
@@ -32,8 +36,10 @@ pub enum ASTClass {
      *      }
      */
     Block(Vec<Box<ASTNode>>),
-    // identifire, block
+    // identifire, block, simulation
     Declare(Box<ASTNode>, Box<ASTNode>, bool),
+    // declare <identifire> interface <block>
+    Interface(Box<ASTNode>, Box<ASTNode>),
     // <id(struct name)>, (<id(member name)>, <number(bit width)>)
     Struct(Box<ASTNode>, Vec<(Box<ASTNode>, Option<Box<ASTNode>>)>),
 
@@ -54,6 +60,13 @@ pub enum ASTClass {
     Input(Box<ASTNode>, Option<Box<ASTNode>>),
     Output(Box<ASTNode>, Option<Box<ASTNode>>),
     InOut(Box<ASTNode>, Option<Box<ASTNode>>),
+    /*
+     *  param_int or param_str, identifire, default value
+     *  e.g.
+     *      param_int WIDTH = 8;
+     *      param_str NAME = "core";
+     */
+    Param(token::Symbol, Box<ASTNode>, Option<Box<ASTNode>>),
 
     // ----- Module ------
     // identifire, block
@@ -63,9 +76,18 @@ pub enum ASTClass {
     //          test in1, in2[2], in3;
     Submodule(Box<ASTNode>, Vec<(Box<ASTNode>, Option<Box<ASTNode>>)>),
     //MacroSubModule(Vec<token::Token>),
-    //      id,         , args
-    ProcName(Box<ASTNode>, Vec<Box<ASTNode>>),
+    //      proc_name <id>(<args>), ... ;
+    //          id,         , args
+    ProcName(Vec<(Box<ASTNode>, Vec<Box<ASTNode>>)>),
+    //   id          , block
+    Proc(Box<ASTNode>, Box<ASTNode>),
+    Finish,
+    // invoke <proc>(<args>);
+    Invoke(Box<ASTNode>),
     StateName(Vec<Box<ASTNode>>),
+    LabelName(Vec<Box<ASTNode>>),
+    // <id>: , a site which `goto` jumps to
+    Label(Box<ASTNode>),
     //  id          ,[12]        , [12]                 , initial value
     Mem(
         Vec<(
@@ -80,8 +102,10 @@ pub enum ASTClass {
     RegAssign(Box<ASTNode>, Box<ASTNode>),
     //   id          , block
     Func(Box<ASTNode>, Option<Box<ASTNode>>, Box<ASTNode>),
-    //  expression       , block
-    Any(Vec<(Box<ASTNode>, Box<ASTNode>)>),
+    //  expression       , block or a statement
+    //  a directive between the arms comes with no body
+    Any(Vec<(Box<ASTNode>, Option<Box<ASTNode>>)>),
+    Alt(Vec<(Box<ASTNode>, Option<Box<ASTNode>>)>),
     Return(Box<ASTNode>),
     Goto(Box<ASTNode>),
     Else,
@@ -92,21 +116,48 @@ pub enum ASTClass {
     State(Box<ASTNode>, Box<ASTNode>),
     // if (<expression>) <block>, <else_node>
     If(Box<ASTNode>, Box<ASTNode>, Option<Box<ASTNode>>),
+    // seq <block>
+    Seq(Box<ASTNode>),
+    // for (<init>; <expression>; <step>) <block or a statement>
+    For(Box<ASTNode>, Box<ASTNode>, Box<ASTNode>, Box<ASTNode>),
+    // while (<expression>) <block or a statement>
+    While(Box<ASTNode>, Box<ASTNode>),
+    // generate (<init>; <expression>; <step>) <block>
+    Generate(Box<ASTNode>, Box<ASTNode>, Box<ASTNode>, Box<ASTNode>),
+    // integer i, j;
+    Integer(Vec<Box<ASTNode>>),
+    // variable v, w[8];
+    //              id    , width
+    Variable(Vec<(Box<ASTNode>, Option<Box<ASTNode>>)>),
 
     // ----- Macros ------
     MacroInclude(Box<ASTNode>),
     MacroUndef(Box<ASTNode>),
     MacroIfdef(Box<ASTNode>),
     MacroIfndef(Box<ASTNode>),
+    // #if <condition>, the condition is kept as it is written
+    MacroIf(String),
+    MacroElif(String),
     MacroElse,
     MacroEndif,
-    MacroDefine(Box<ASTNode>, Option<String>),
+    // #define <name> [<value>] [// <comment>]
+    MacroDefine(Box<ASTNode>, Option<MacroValue>, Option<String>),
+    // #error <message>
+    MacroError(String),
+    // a defined name used as a statement, and whether `;` follows it
+    MacroStatement(Box<ASTNode>, bool),
 
     // wire enable, data[12];
     //              id    , width
     Wire(Vec<(Box<ASTNode>, Option<Box<ASTNode>>)>),
     //          id       , width       , initial_value
     Reg(Vec<(Box<ASTNode>, Option<Box<ASTNode>>, Option<Box<ASTNode>>)>),
+    // my_struct wire a, b;
+    //          <id(struct name)>, ids
+    StructWire(Box<ASTNode>, Vec<Box<ASTNode>>),
+    // my_struct reg a = 0, b;
+    //          <id(struct name)>, (id, initial_value)
+    StructReg(Box<ASTNode>, Vec<(Box<ASTNode>, Option<Box<ASTNode>>)>),
 
     //          operand     , operation   , operand,   is required parances
     Expression(Box<ASTNode>, Box<ASTNode>, Box<ASTNode>),
@@ -114,27 +165,113 @@ pub enum ASTClass {
     BitslicedExpr(Box<ASTNode>, Box<ASTNode>),
     //          unary operator, expression
     UnaryOperation(Box<ASTNode>, Box<ASTNode>),
+    //      condition  , then        , else
+    Ternary(Box<ASTNode>, Box<ASTNode>, Box<ASTNode>),
+    // ( <expression> ), kept as the user wrote it
+    Paren(Box<ASTNode>),
+    // { <expression>, ... }, also the target of an assignment
+    Concat(Vec<Box<ASTNode>>),
+    // { <count>{ <expression>, ... } }
+    Repeat(Box<ASTNode>, Vec<Box<ASTNode>>),
+    //  `#`(sign) or `'`(zero), width, expression
+    //  e.g. 16#(a), 32'(b)
+    Extension(token::Symbol, Box<ASTNode>, Box<ASTNode>),
     CPPStyleComment(String),
     CStyleComment(Vec<String>),
+    // placeholder for a statement which could not be parsed
+    Error,
     //     Newline,
     EndOfProgram,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct ASTNode {
     pub class: ASTClass,
-    pub position: usize,
+    pub span: token::Span,
 }
 
-impl ASTNode {
-    pub fn new(class: ASTClass, position: usize) -> ASTNode {
-        ASTNode {
-            class: class,
-            position: position,
+/*
+ * the value of #define, the tokens of the rest of the line but a `//` comment.
+ * the tokens are printed as they are spelled, with a space between two of them
+ * where the source has any. a value continued with `\` is put on one line.
+ */
+#[derive(Debug, Clone)]
+pub struct MacroValue {
+    pub tokens: Vec<token::Token>,
+}
+
+impl MacroValue {
+    pub fn new(tokens: Vec<token::Token>) -> MacroValue {
+        MacroValue { tokens }
+    }
+
+    // whether the source has a space before each token
+    fn spaced(&self) -> Vec<bool> {
+        let mut last_end: Option<usize> = None;
+        self.tokens
+            .iter()
+            .map(|t| {
+                let spaced = last_end.is_some_and(|end| end < t.span.start);
+                last_end = Some(t.span.end);
+                spaced
+            })
+            .collect()
+    }
+}
+
+// values written in different places are the same if their tokens and spaces are
+impl PartialEq for MacroValue {
+    fn eq(&self, other: &MacroValue) -> bool {
+        self.tokens.len() == other.tokens.len()
+            && self
+                .tokens
+                .iter()
+                .zip(other.tokens.iter())
+                .all(|(a, b)| a.class == b.class)
+            && self.spaced() == other.spaced()
+    }
+}
+
+impl fmt::Display for MacroValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", token::source_text(&self.tokens))
+    }
+}
+
+// lexes a line, e.g. MacroValue::from("(16 * 2)")
+impl From<&str> for MacroValue {
+    fn from(s: &str) -> MacroValue {
+        let mut b = s.as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut tokens = vec![];
+        loop {
+            let t = l.next(false);
+            match t.class {
+                token::TokenClass::Newline | token::TokenClass::EndOfProgram => break,
+                _ => tokens.push(t),
+            }
         }
+        MacroValue::new(tokens)
+    }
+}
+
+// the span is where the node came from, it is not a part of the tree.
+impl PartialEq for ASTNode {
+    fn eq(&self, other: &ASTNode) -> bool {
+        self.class == other.class
+    }
+}
+
+impl ASTNode {
+    pub fn new(class: ASTClass, span: token::Span) -> ASTNode {
+        ASTNode { class, span }
     }
 
     pub fn generate(&self) -> LinkedList<String> {
+        self.generate_with(&Config::default())
+    }
+
+    pub fn generate_with(&self, config: &Config) -> LinkedList<String> {
         let mut list = LinkedList::new();
         match self.class {
             ASTClass::Declare(ref id, ref block, ref is_sim) => {
@@ -143,18 +280,26 @@ impl ASTNode {
                 } else {
                     list.push_back(format!("declare {}", id));
                 }
-                list.append(&mut block.generate());
+                list.append(&mut block.generate_with(config));
+            }
+            ASTClass::Interface(ref id, ref block) => {
+                list.push_back(format!("declare {} interface", id));
+                list.append(&mut block.generate_with(config));
             }
             ASTClass::Module(ref id, ref block) => {
                 list.push_back(format!("module {}", id));
-                list.append(&mut block.generate());
+                list.append(&mut block.generate_with(config));
             }
             ASTClass::Struct(ref id, ref member_info) => {
                 list.push_back(format!("struct {}", id));
                 let mut struct_members = LinkedList::new();
                 for c in member_info {
                     if let Some(ref width) = c.1 {
-                        struct_members.push_back(format!("{}[{}]", c.0, width));
+                        struct_members.push_back(format!(
+                            "{}[{}]",
+                            c.0,
+                            get_top!(width, config)
+                        ));
                     } else {
                         struct_members.push_back(format!("{}", c.0));
                     }
@@ -169,117 +314,160 @@ impl ASTNode {
                 list.push_back("}".to_string());
             }
             ASTClass::Block(ref contents) => {
-                for c in contents {
-                    match c.class {
-                        ASTClass::Any(_) => {
-                            list.append(&mut c.generate());
-                        }
-                        ASTClass::Func(_, _, _) => {
-                            list.append(&mut c.generate());
-                        }
-                        ASTClass::If(_, _, _) => {
-                            list.append(&mut c.generate());
-                        }
-                        ASTClass::State(_, _) => {
-                            list.append(&mut c.generate());
-                        }
-                        ASTClass::CPPStyleComment(_) => {
-                            list.append(&mut c.generate());
-                        }
-                        //TODO
-                        _ => {
-                            list.push_back(format!("{};", get_top!(c)));
-                        }
+                let config = &indented(config);
+                let mut i = 0;
+                while i < contents.len() {
+                    let ports = contents[i..]
+                        .iter()
+                        .take_while(|c| is_port_declaration(c))
+                        .count();
+                    // only a run with param_* in it is aligned
+                    let aligned = contents[i..i + ports].iter().any(|c| is_param(c));
+                    let defines =
+                        contents[i..].iter().take_while(|c| is_define(c)).count();
+                    if aligned {
+                        list.append(&mut aligned_declarations(
+                            &contents[i..i + ports],
+                            config,
+                        ));
+                        i += ports;
+                    } else if defines > 0 {
+                        list.append(&mut aligned_defines(
+                            &contents[i..i + defines],
+                            config,
+                        ));
+                        i += defines;
+                    } else {
+                        list.append(&mut statement_lines(&contents[i], config));
+                        i += 1;
                     }
                 }
                 let mut nm: LinkedList<String> =
-                    list.iter().map(|c| format!("    {}", c)).collect();
+                    list.iter().map(|c| indent_line(c, config)).collect();
                 nm.push_front("{".to_string());
                 nm.push_back("}".to_string());
                 return nm;
             }
             ASTClass::Any(ref contents) => {
-                for (expr, block) in contents {
-                    let expr_str = if let Some(top) = expr.generate().pop_front() {
-                        top
-                    } else {
-                        panic!();
-                    };
-                    list.push_back(format!("{}:", expr_str));
-                    list.append(&mut block.generate());
-                }
-                let mut nm: LinkedList<String> =
-                    list.iter().map(|c| "    ".to_string() + c).collect();
-                nm.push_front("{".to_string());
-                nm.push_back("}".to_string());
+                let mut nm = arm_lines(contents, config);
                 nm.push_front("any".to_string());
                 return nm;
             }
+            ASTClass::Alt(ref contents) => {
+                let mut nm = arm_lines(contents, config);
+                nm.push_front("alt".to_string());
+                return nm;
+            }
             ASTClass::Else => {
                 list.push_back("else".to_string());
             }
             ASTClass::Expression(ref operand1, ref operator, ref operand2) => {
+                let prec = precedence(self).unwrap();
+                // the tree is left associative, so an operand on the right side
+                // needs parentheses even for the same precedence
                 list.push_back(format!(
-                    //                     "({} {} {})",
                     "{} {} {}",
-                    get_top!(operand1),
+                    parenthesize(
+                        operand1,
+                        precedence(operand1).is_some_and(|p| p < prec),
+                        config
+                    ),
                     operator,
-                    get_top!(operand2)
+                    parenthesize(
+                        operand2,
+                        precedence(operand2).is_some_and(|p| p <= prec),
+                        config
+                    )
                 ));
             }
             ASTClass::Identifire(ref id) => {
-                list.push_back(format!("{}", id));
+                list.push_back(id.to_string());
             }
             ASTClass::ModulePort(ref id, ref port) => {
-                list.push_back(format!("{}.{}", id, port));
+                list.push_back(format!("{}.{}", get_top!(id, config), port));
             }
             ASTClass::FuncCall(ref id, ref args, ref second_some) => {
                 let arg_str = args
                     .iter()
-                    .map(|id| format!("{}", get_top!(id)))
+                    .map(|id| get_top!(id, config))
                     .collect::<Vec<String>>()
                     .join(", ");
 
+                let callee = get_top!(id, config);
                 if let Some(second) = second_some {
-                    list.push_back(format!("{}.{}({})", id, second, arg_str));
+                    let second = get_top!(second, config);
+                    list.push_back(format!("{}.{}({})", callee, second, arg_str));
                 } else {
-                    list.push_back(format!("{}({})", id, arg_str));
+                    list.push_back(format!("{}({})", callee, arg_str));
                 }
             }
             ASTClass::Number(ref num) => {
-                list.push_back(format!("{}", num));
+                list.push_back(num.to_string());
             }
-            ASTClass::String(ref _id) => {
-                not_implemented!();
+            ASTClass::String(ref s) => {
+                list.push_back(format!("\"{}\"", s));
             }
             ASTClass::Submodule(ref submodule, ref contents) => {
                 let l: Vec<String> = contents
                     .iter()
-                    .map(|ref r| {
+                    .map(|r| {
                         let mut def = format!("{}", r.0);
                         if let Some(ref width) = r.1 {
-                            def.push_str(&format!("[{}]", get_top!(width)));
+                            def.push_str(&format!("[{}]", get_top!(width, config)));
                         }
-                        return def;
+                        def
                     })
                     .collect();
                 list.push_back(format!("{} {}", submodule, l.join(", ")));
             }
             ASTClass::BitSlice(ref msb, ref some_lsb) => {
-                let m = get_top!(msb);
+                let m = get_top!(msb, config);
                 if let Some(lsb) = some_lsb {
-                    list.push_back(format!("{}:{}", m, get_top!(lsb)));
+                    list.push_back(format!("{}:{}", m, get_top!(lsb, config)));
                 } else {
-                    list.push_back(format!("{}", m));
+                    list.push_back(m.to_string());
                 }
             }
+            ASTClass::Paren(ref expr) => {
+                list.push_back(format!("({})", get_top!(expr, config)));
+            }
+            ASTClass::Ternary(ref cond, ref then_expr, ref else_expr) => {
+                list.push_back(format!(
+                    "{} ? {} : {}",
+                    get_top!(cond, config),
+                    get_top!(then_expr, config),
+                    get_top!(else_expr, config)
+                ));
+            }
+            ASTClass::Concat(ref items) => {
+                list.push_back(format!("{{{}}}", expression_list(items, config)));
+            }
+            ASTClass::Repeat(ref count, ref items) => {
+                list.push_back(format!(
+                    "{{{}{{{}}}}}",
+                    get_top!(count, config),
+                    expression_list(items, config)
+                ));
+            }
+            ASTClass::Extension(ref kind, ref width, ref expr) => {
+                list.push_back(format!(
+                    "{}{}({})",
+                    get_top!(width, config),
+                    kind,
+                    get_top!(expr, config)
+                ));
+            }
             ASTClass::BitslicedExpr(ref expr, ref bitslice) => {
-                list.push_back(format!("{}[{}]", get_top!(expr), get_top!(bitslice)));
+                list.push_back(format!(
+                    "{}[{}]",
+                    parenthesize(expr, precedence(expr).is_some(), config),
+                    get_top!(bitslice, config)
+                ));
             }
             ASTClass::FuncIn(ref id, ref args, ref result) => {
                 let arg_str = args
                     .iter()
-                    .map(|id| format!("{}", id))
+                    .map(|arg| get_top!(arg, config))
                     .collect::<Vec<String>>()
                     .join(", ");
                 if let Some(return_port) = result {
@@ -291,13 +479,25 @@ impl ASTNode {
                     list.push_back(format!("func_in {}({})", id, arg_str));
                 }
             }
-            ASTClass::FuncOut(ref _id, ref _args, ref _result) => {
-                not_implemented!();
+            ASTClass::FuncOut(ref id, ref args, ref result) => {
+                let arg_str = args
+                    .iter()
+                    .map(|arg| get_top!(arg, config))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                if let Some(return_port) = result {
+                    list.push_back(format!(
+                        "func_out {}({}) : {}",
+                        id, arg_str, return_port
+                    ));
+                } else {
+                    list.push_back(format!("func_out {}({})", id, arg_str));
+                }
             }
             ASTClass::FuncSelf(ref id, ref args, ref result) => {
                 let arg_str = args
                     .iter()
-                    .map(|id| format!("{}", id))
+                    .map(|arg| get_top!(arg, config))
                     .collect::<Vec<String>>()
                     .join(", ");
                 if let Some(return_port) = result {
@@ -311,37 +511,99 @@ impl ASTNode {
             }
             ASTClass::Input(ref id, ref some_expr) => {
                 if let Some(expr) = some_expr {
-                    list.push_back(format!("input {}[{}]", id, get_top!(expr)));
+                    list.push_back(format!("input {}[{}]", id, get_top!(expr, config)));
                 } else {
                     list.push_back(format!("input {}", id));
                 }
             }
             ASTClass::Output(ref id, ref some_expr) => {
                 if let Some(expr) = some_expr {
-                    list.push_back(format!("output {}[{}]", id, get_top!(expr)));
+                    list.push_back(format!("output {}[{}]", id, get_top!(expr, config)));
                 } else {
                     list.push_back(format!("output {}", id));
                 }
             }
             ASTClass::InOut(ref id, ref some_expr) => {
                 if let Some(expr) = some_expr {
-                    list.push_back(format!("inout {}[{}]", id, get_top!(expr)));
+                    list.push_back(format!("inout {}[{}]", id, get_top!(expr, config)));
                 } else {
                     list.push_back(format!("inout {}", id));
                 }
             }
-            ASTClass::Mem(ref _contents) => {
-                not_implemented!();
+            ASTClass::Param(ref kind, ref id, ref default) => {
+                if let Some(value) = default {
+                    list.push_back(format!(
+                        "{} {} = {}",
+                        kind,
+                        id,
+                        get_top!(value, config)
+                    ));
+                } else {
+                    list.push_back(format!("{} {}", kind, id));
+                }
+            }
+            ASTClass::Mem(ref contents) => {
+                let mut defines: LinkedList<String> = LinkedList::new();
+                for (id, width, width2, init) in contents {
+                    let mut def = format!("{}[{}]", id, get_top!(width, config));
+                    if let Some(ref w) = width2 {
+                        def.push_str(&format!("[{}]", get_top!(w, config)));
+                    }
+                    let mut lines = LinkedList::new();
+                    match init {
+                        Some(ref values) if values.len() > config.mem_values_per_line => {
+                            lines.push_back(format!("{} =", def));
+                            lines.push_back("{".to_string());
+                            let per_line = config.mem_values_per_line.max(1);
+                            let rows: Vec<&[Box<ASTNode>]> =
+                                values.chunks(per_line).collect();
+                            for (i, row) in rows.iter().enumerate() {
+                                let mut row_str = row
+                                    .iter()
+                                    .map(|v| get_top!(v, config))
+                                    .collect::<Vec<String>>()
+                                    .join(", ");
+                                if i + 1 < rows.len() {
+                                    row_str.push(',');
+                                }
+                                lines.push_back(format!("    {}", row_str));
+                            }
+                            lines.push_back("}".to_string());
+                        }
+                        Some(ref values) => {
+                            let values_str = values
+                                .iter()
+                                .map(|v| get_top!(v, config))
+                                .collect::<Vec<String>>()
+                                .join(", ");
+                            lines.push_back(format!("{} = {{{}}}", def, values_str));
+                        }
+                        None => lines.push_back(def),
+                    }
+                    // mem a[2], b[4] = {...};
+                    match defines.pop_back() {
+                        Some(last) => {
+                            let first = lines.pop_front().unwrap();
+                            defines.push_back(format!("{}, {}", last, first));
+                        }
+                        None => {
+                            let first = lines.pop_front().unwrap();
+                            defines.push_back(format!("mem {}", first));
+                        }
+                    }
+                    defines.append(&mut lines);
+                }
+                list.append(&mut defines);
             }
             ASTClass::Wire(ref contents) => {
                 let l: Vec<String> = contents
                     .iter()
-                    .map(|ref r| {
+                    .map(|r| {
                         let mut def = format!("{}", r.0);
                         if let Some(ref width) = r.1 {
-                            def.push_str(&format!("[{}]", get_top!(width)));
+                            def.push_str(&format!("[{}]", get_top!(width, config)));
                         }
-                        return def;
+                        def
                     })
                     .collect();
                 list.push_back(format!("wire {}", l.join(", ")));
@@ -349,19 +611,37 @@ impl ASTNode {
             ASTClass::Reg(ref contents) => {
                 let l: Vec<String> = contents
                     .iter()
-                    .map(|ref r| {
+                    .map(|r| {
                         let mut define = format!("{}", r.0);
                         if let Some(ref width) = r.1 {
-                            define.push_str(&format!("[{}]", get_top!(width)))
+                            define.push_str(&format!("[{}]", get_top!(width, config)))
                         }
                         if let Some(ref init) = r.2 {
-                            define.push_str(&format!(" = {}", init));
+                            define.push_str(&format!(" = {}", get_top!(init, config)));
                         }
-                        return define;
+                        define
                     })
                     .collect();
                 list.push_back(format!("reg {}", l.join(", ")));
             }
+            ASTClass::StructWire(ref struct_id, ref ids) => {
+                let ids_str = ids
+                    .iter()
+                    .map(|id| format!("{}", id))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                list.push_back(format!("{} wire {}", struct_id, ids_str));
+            }
+            ASTClass::StructReg(ref struct_id, ref regs) => {
+                let l: Vec<String> = regs
+                    .iter()
+                    .map(|(id, init)| match init {
+                        Some(value) => format!("{} = {}", id, get_top!(value, config)),
+                        None => format!("{}", id),
+                    })
+                    .collect();
+                list.push_back(format!("{} reg {}", struct_id, l.join(", ")));
+            }
             //             ASTClass::Newline => {
             //                 not_implemented!()
             //             }
@@ -371,8 +651,30 @@ impl ASTNode {
             ASTClass::CStyleComment(ref comments) => {
                 list.push_back(format!("/*{}*/", comments.join("\n")));
             }
-            ASTClass::ProcName(ref _id, ref _args) => {
-                not_implemented!();
+            ASTClass::ProcName(ref procs) => {
+                let procs_str = procs
+                    .iter()
+                    .map(|(id, args)| {
+                        let arg_str = args
+                            .iter()
+                            .map(|arg| get_top!(arg, config))
+                            .collect::<Vec<String>>()
+                            .join(", ");
+                        format!("{}({})", id, arg_str)
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                list.push_back(format!("proc_name {}", procs_str));
+            }
+            ASTClass::Proc(ref id, ref block) => {
+                list.push_back(format!("proc {}", id));
+                list.append(&mut block.generate_with(config));
+            }
+            ASTClass::Finish => {
+                list.push_back("finish".to_string());
+            }
+            ASTClass::Invoke(ref call) => {
+                list.push_back(format!("invoke {}", get_top!(call, config)));
             }
             ASTClass::StateName(ref ids) => {
                 let ids_str = ids
@@ -382,11 +684,45 @@ impl ASTNode {
                     .join(", ");
                 list.push_back(format!("state_name {}", ids_str));
             }
+            ASTClass::Integer(ref ids) => {
+                let ids_str = ids
+                    .iter()
+                    .map(|id_node| format!("{}", id_node))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                list.push_back(format!("integer {}", ids_str));
+            }
+            ASTClass::Variable(ref contents) => {
+                let l: Vec<String> = contents
+                    .iter()
+                    .map(|r| {
+                        let mut def = format!("{}", r.0);
+                        if let Some(ref width) = r.1 {
+                            def.push_str(&format!("[{}]", get_top!(width, config)));
+                        }
+                        def
+                    })
+                    .collect();
+                list.push_back(format!("variable {}", l.join(", ")));
+            }
+            ASTClass::LabelName(ref ids) => {
+                let ids_str = ids
+                    .iter()
+                    .map(|id_node| format!("{}", id_node))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                list.push_back(format!("label_name {}", ids_str));
+            }
+            ASTClass::Label(ref id) => {
+                list.push_back(format!("{}:", id));
+            }
             ASTClass::Assign(ref id, ref expr) => {
-                list.push_back(format!("{} = {}", get_top!(id), get_top!(expr)));
+                let head = format!("{} = ", get_top!(id, config));
+                list.append(&mut value_lines(head, expr, config));
             }
             ASTClass::RegAssign(ref id, ref expr) => {
-                list.push_back(format!("{} := {}", id, get_top!(expr)));
+                let head = format!("{} := ", get_top!(id, config));
+                list.append(&mut value_lines(head, expr, config));
             }
             ASTClass::Func(ref id, ref func, ref block) => {
                 if let Some(fname) = func {
@@ -394,29 +730,52 @@ impl ASTNode {
                 } else {
                     list.push_back(format!("func {}", id));
                 }
-                list.append(&mut block.generate());
+                list.append(&mut block.generate_with(config));
             }
             ASTClass::Return(ref value) => {
-                list.push_back(format!("return {}", get_top!(value)));
+                list.append(&mut value_lines("return ".to_string(), value, config));
             }
             ASTClass::Goto(ref id) => {
                 list.push_back(format!("goto {}", id));
             }
             ASTClass::State(ref id, ref block) => {
                 list.push_back(format!("state {}", id));
-                list.append(&mut block.generate());
+                list.append(&mut block.generate_with(config));
+            }
+            ASTClass::Seq(ref block) => {
+                list.push_back("seq".to_string());
+                list.append(&mut block.generate_with(config));
+            }
+            ASTClass::For(ref init, ref cond, ref step, ref block) => {
+                list.push_back(format!(
+                    "for ({}; {}; {})",
+                    get_top!(init, config),
+                    get_top!(cond, config),
+                    get_top!(step, config)
+                ));
+                list.append(&mut body_lines(block, config));
+            }
+            ASTClass::Generate(ref init, ref cond, ref step, ref block) => {
+                list.push_back(format!(
+                    "generate ({}; {}; {})",
+                    get_top!(init, config),
+                    get_top!(cond, config),
+                    get_top!(step, config)
+                ));
+                list.append(&mut block.generate_with(config));
+            }
+            ASTClass::While(ref cond, ref block) => {
+                list.push_back(format!("while ({})", get_top!(cond, config)));
+                list.append(&mut body_lines(block, config));
             }
             ASTClass::If(ref expr, ref if_block, ref else_block) => {
-                list.push_back(format!("if ({})", get_top!(expr)));
-                list.append(&mut if_block.generate());
+                list.push_back(format!("if ({})", get_top!(expr, config)));
+                list.append(&mut body_lines(if_block, config));
                 if let Some(block) = else_block {
-                    list.push_back(format!("else"));
-                    list.append(&mut block.generate());
+                    list.push_back("else".to_string());
+                    list.append(&mut body_lines(block, config));
                 }
             }
-            ASTClass::InOut(ref _id, ref _expr) => {
-                not_implemented!();
-            }
             ASTClass::Operator(ref _op) => {
                 not_implemented!();
             }
@@ -424,14 +783,28 @@ impl ASTNode {
                 list.push_back(format!("{}", op));
             }
             ASTClass::UnaryOperation(ref a, ref b) => {
-                list.push_back(format!("{}{}", get_top!(a), get_top!(b)));
+                let left = parenthesize(a, precedence(a).is_some(), config);
+                let right = parenthesize(b, precedence(b).is_some(), config);
+                // - -x must not be read back as --x, nor & &x as &&x
+                let glued = match (left.chars().last(), right.chars().next()) {
+                    (Some(l), Some(r)) => l == r && "-+&|".contains(l),
+                    _ => false,
+                };
+                if glued {
+                    list.push_back(format!("{} {}", left, right));
+                } else {
+                    list.push_back(format!("{}{}", left, right));
+                }
             }
-            ASTClass::MacroDefine(ref id, ref value) => {
+            ASTClass::MacroDefine(ref id, ref value, ref comment) => {
+                let mut line = format!("#define {}", id);
                 if let Some(v) = value {
-                    list.push_back(format!("#define {} {}", id, v));
-                } else {
-                    list.push_back(format!("#define {}", id));
+                    line = format!("{} {}", line, v);
                 }
+                if let Some(c) = comment {
+                    line = format!("{} //{}", line, c);
+                }
+                list.push_back(line);
             }
             ASTClass::MacroInclude(ref path) => {
                 list.push_back(format!("#include {}", path));
@@ -442,15 +815,30 @@ impl ASTNode {
             ASTClass::MacroIfndef(ref id) => {
                 list.push_back(format!("#ifndef {}", id));
             }
+            ASTClass::MacroIf(ref cond) => {
+                list.push_back(format!("#if {}", cond));
+            }
+            ASTClass::MacroElif(ref cond) => {
+                list.push_back(format!("#elif {}", cond));
+            }
+            ASTClass::MacroError(ref message) => {
+                list.push_back(format!("#error {}", message).trim_end().to_string());
+            }
+            ASTClass::MacroStatement(ref id, semicolon) => {
+                list.push_back(format!("{}{}", id, if semicolon { ";" } else { "" }));
+            }
             ASTClass::MacroElse => {
-                list.push_back(format!("#else"));
+                list.push_back("#else".to_string());
             }
             ASTClass::MacroEndif => {
-                list.push_back(format!("#endif"));
+                list.push_back("#endif".to_string());
             }
             ASTClass::MacroUndef(ref id) => {
                 list.push_back(format!("#undef {}", id));
             }
+            ASTClass::Error => {
+                list.push_back("/* syntax error */".to_string());
+            }
             ASTClass::EndOfProgram => {
                 not_implemented!();
             }
@@ -462,17 +850,396 @@ impl ASTNode {
     }
 }
 
+/*
+ * a statement in a block, the compound ones take several lines
+ * and the others are terminated by `;`
+ */
+fn statement_lines(c: &ASTNode, config: &Config) -> LinkedList<String> {
+    match c.class {
+        ASTClass::Any(_)
+        | ASTClass::Alt(_)
+        | ASTClass::Func(_, _, _)
+        | ASTClass::If(_, _, _)
+        | ASTClass::State(_, _)
+        | ASTClass::Proc(_, _)
+        | ASTClass::Seq(_)
+        | ASTClass::For(_, _, _, _)
+        | ASTClass::While(_, _)
+        | ASTClass::Generate(_, _, _, _)
+        | ASTClass::Label(_)
+        | ASTClass::MacroInclude(_)
+        | ASTClass::MacroUndef(_)
+        | ASTClass::MacroIfdef(_)
+        | ASTClass::MacroIfndef(_)
+        | ASTClass::MacroIf(_)
+        | ASTClass::MacroElif(_)
+        | ASTClass::MacroElse
+        | ASTClass::MacroEndif
+        | ASTClass::MacroDefine(_, _, _)
+        | ASTClass::MacroError(_)
+        | ASTClass::MacroStatement(_, _)
+        | ASTClass::CPPStyleComment(_)
+        | ASTClass::CStyleComment(_)
+        | ASTClass::Block(_) => c.generate_with(config),
+        //TODO
+        _ => {
+            let mut list = c.generate_with(config);
+            if let Some(last) = list.back_mut() {
+                last.push(';');
+            }
+            list
+        }
+    }
+}
+
+fn is_port_declaration(c: &ASTNode) -> bool {
+    matches!(
+        c.class,
+        ASTClass::Input(_, _)
+            | ASTClass::Output(_, _)
+            | ASTClass::InOut(_, _)
+            | ASTClass::Param(_, _, _)
+    )
+}
+
+fn is_param(c: &ASTNode) -> bool {
+    matches!(c.class, ASTClass::Param(_, _, _))
+}
+
+/*
+ * consecutive input/output/inout/param_* lines with their names in a column
+ *  input     a[8];
+ *  param_int WIDTH = 8;
+ */
+fn aligned_declarations(decls: &[Box<ASTNode>], config: &Config) -> LinkedList<String> {
+    let lines: Vec<String> = decls.iter().map(|c| get_top!(c, config)).collect();
+    let split = |l: &String| -> (String, String) {
+        let mut kv = l.splitn(2, ' ');
+        let keyword = kv.next().unwrap_or("").to_string();
+        (keyword, kv.next().unwrap_or("").to_string())
+    };
+    let width = lines.iter().map(|l| split(l).0.len()).max().unwrap_or(0);
+    lines
+        .iter()
+        .map(|l| {
+            let (keyword, rest) = split(l);
+            format!("{:width$} {};", keyword, rest, width = width)
+        })
+        .collect()
+}
+
+/*
+ * consecutive #define with their values in a column,
+ * and the trailing comments in another one
+ *  #define WIDTH      8  // bits
+ *  #define ADDR_WIDTH 16 // bytes
+ */
+pub fn aligned_defines(defines: &[Box<ASTNode>], config: &Config) -> LinkedList<String> {
+    let width = defines
+        .iter()
+        .filter_map(|d| match d.class {
+            ASTClass::MacroDefine(ref id, Some(_), _) => Some(format!("{}", id).len()),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<(String, Option<&String>)> = defines
+        .iter()
+        .map(|d| match d.class {
+            ASTClass::MacroDefine(ref id, Some(ref value), ref comment) => (
+                format!(
+                    "#define {:width$} {}",
+                    format!("{}", id),
+                    value,
+                    width = width
+                ),
+                comment.as_ref(),
+            ),
+            ASTClass::MacroDefine(ref id, None, ref comment) => {
+                (format!("#define {}", id), comment.as_ref())
+            }
+            _ => (get_top!(d, config), None),
+        })
+        .collect();
+    let column = lines
+        .iter()
+        .filter(|(_, comment)| comment.is_some())
+        .map(|(l, _)| l.len())
+        .max()
+        .unwrap_or(0);
+    lines
+        .into_iter()
+        .map(|(l, comment)| match comment {
+            Some(c) => format!("{:column$} //{}", l, c, column = column),
+            None => l,
+        })
+        .collect()
+}
+
+pub fn is_define(c: &ASTNode) -> bool {
+    matches!(c.class, ASTClass::MacroDefine(_, _, _))
+}
+
+fn is_directive(c: &ASTNode) -> bool {
+    matches!(
+        c.class,
+        ASTClass::MacroInclude(_)
+            | ASTClass::MacroUndef(_)
+            | ASTClass::MacroIfdef(_)
+            | ASTClass::MacroIfndef(_)
+            | ASTClass::MacroIf(_)
+            | ASTClass::MacroElif(_)
+            | ASTClass::MacroElse
+            | ASTClass::MacroEndif
+            | ASTClass::MacroDefine(_, _, _)
+            | ASTClass::MacroError(_)
+    )
+}
+
+fn is_comment(c: &ASTNode) -> bool {
+    matches!(
+        c.class,
+        ASTClass::CPPStyleComment(_) | ASTClass::CStyleComment(_)
+    )
+}
+
+/*
+ * `#ifndef X` `#define X` at the top, closed by the last #endif
+ */
+fn has_include_guard(nodes: &[Box<ASTNode>]) -> bool {
+    let guarded = match (nodes.first(), nodes.get(1)) {
+        (Some(first), Some(second)) => match (&first.class, &second.class) {
+            (ASTClass::MacroIfndef(ref a), ASTClass::MacroDefine(ref b, None, _)) => {
+                format!("{}", a) == format!("{}", b)
+            }
+            _ => false,
+        },
+        _ => false,
+    };
+    if !guarded {
+        return false;
+    }
+    let mut depth = 0;
+    for (i, c) in nodes.iter().enumerate() {
+        match c.class {
+            ASTClass::MacroIfdef(_) | ASTClass::MacroIfndef(_) | ASTClass::MacroIf(_) => {
+                depth += 1
+            }
+            ASTClass::MacroEndif => {
+                depth -= 1;
+                if depth == 0 {
+                    return i == nodes.len() - 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/*
+ * the top level of a header file, e.g. foo.nh
+ *  - the include guard is kept around the whole file, set apart by empty lines
+ *  - consecutive #define are aligned
+ *  - an empty line between a group of directives and a declaration,
+ *    and between declarations. a comment stays with what follows it.
+ */
+pub fn header_lines(nodes: &[Box<ASTNode>], config: &Config) -> LinkedList<String> {
+    let mut list = LinkedList::new();
+    if !has_include_guard(nodes) {
+        list.append(&mut header_body(nodes, config));
+        return list;
+    }
+    let (ifndef, define, endif) = (&nodes[0], &nodes[1], &nodes[nodes.len() - 1]);
+    let body = &nodes[2..nodes.len() - 1];
+    list.push_back(get_top!(ifndef, config));
+    list.push_back(get_top!(define, config));
+    if !body.is_empty() {
+        list.push_back(String::new());
+        list.append(&mut header_body(body, config));
+        list.push_back(String::new());
+    }
+    list.push_back(get_top!(endif, config));
+    list
+}
+
+fn header_body(nodes: &[Box<ASTNode>], config: &Config) -> LinkedList<String> {
+    let mut list = LinkedList::new();
+    let mut i = 0;
+    while i < nodes.len() {
+        let c = &nodes[i];
+        if i > 0 {
+            let prev = &nodes[i - 1];
+            let together = is_comment(prev) || (is_directive(prev) && is_directive(c));
+            if !together {
+                list.push_back(String::new());
+            }
+        }
+        let run = nodes[i..].iter().take_while(|d| is_define(d)).count();
+        if run == 0 {
+            list.append(&mut c.generate_with(config));
+            i += 1;
+        } else {
+            list.append(&mut aligned_defines(&nodes[i..i + run], config));
+            i += run;
+        }
+    }
+    list
+}
+
+/*
+ * the body of a loop, a statement without braces goes on the next line
+ */
+fn body_lines(block: &ASTNode, config: &Config) -> LinkedList<String> {
+    match block.class {
+        ASTClass::Block(_) => block.generate_with(config),
+        _ => statement_lines(block, &indented(config))
+            .iter()
+            .map(|c| format!("    {}", c))
+            .collect(),
+    }
+}
+
+/*
+ * the body of `any` and `alt`, an arm with a single simple statement
+ * is kept on the line of its condition
+ */
+fn arm_lines(
+    arms: &[(Box<ASTNode>, Option<Box<ASTNode>>)],
+    config: &Config,
+) -> LinkedList<String> {
+    let config = &indented(config);
+    let mut list = LinkedList::new();
+    for (expr, block) in arms {
+        let expr_str = get_top!(expr, config);
+        let block = match block {
+            Some(block) => block,
+            // a directive
+            None => {
+                list.push_back(expr_str);
+                continue;
+            }
+        };
+        let mut lines = statement_lines(block, config);
+        match block.class {
+            ASTClass::Block(_) => {
+                list.push_back(format!("{}:", expr_str));
+                list.append(&mut lines);
+            }
+            _ if lines.len() == 1 => {
+                list.push_back(format!("{}: {}", expr_str, lines.pop_front().unwrap()));
+            }
+            _ => {
+                list.push_back(format!("{}:", expr_str));
+                list.append(&mut lines.iter().map(|c| format!("    {}", c)).collect());
+            }
+        }
+    }
+    let mut nm: LinkedList<String> =
+        list.iter().map(|c| indent_line(c, config)).collect();
+    nm.push_front("{".to_string());
+    nm.push_back("}".to_string());
+    nm
+}
+
+/*
+ * precedence of the binary operator at the top of the node,
+ * None if the node is not a binary expression.
+ */
+fn precedence(node: &ASTNode) -> Option<usize> {
+    if let ASTClass::Expression(_, ref op, _) = node.class {
+        if let ASTClass::Operator(ref op) = op.class {
+            return Some(op.precedence());
+        }
+    }
+    None
+}
+
+/*
+ * shifts a line in a block by 4 columns. a preprocessor directive is kept
+ * at column 0 however deep the block is, unless indent_directives is set.
+ */
+fn indent_line(line: &str, config: &Config) -> String {
+    if line.starts_with('#') && !config.indent_directives {
+        line.to_string()
+    } else {
+        format!("    {}", line)
+    }
+}
+
+// the config for the contents of a block, which are shifted by 4 columns
+fn indented(config: &Config) -> Config {
+    Config {
+        max_width: config.max_width.saturating_sub(4),
+        ..config.clone()
+    }
+}
+
+/*
+ * `<head><expression>` of a statement. a ternary which does not fit in
+ * max_width is broken with `?` and `:` in one column
+ *
+ *  out = sel
+ *      ? a
+ *      : b;
+ */
+fn value_lines(head: String, expr: &ASTNode, config: &Config) -> LinkedList<String> {
+    let mut list = LinkedList::new();
+    let line = format!("{}{}", head, get_top!(expr, config));
+    // `;` follows
+    if line.chars().count() < config.max_width {
+        list.push_back(line);
+        return list;
+    }
+    let mut lines = ternary_lines(expr, config).into_iter();
+    list.push_back(format!("{}{}", head, lines.next().unwrap()));
+    list.extend(lines.map(|l| format!("    {}", l)));
+    list
+}
+
+// a chain of ternaries in the else side is flattened into the same column
+fn ternary_lines(expr: &ASTNode, config: &Config) -> Vec<String> {
+    if let ASTClass::Ternary(ref cond, ref then_expr, ref else_expr) = expr.class {
+        let mut lines = vec![
+            get_top!(cond, config),
+            format!("? {}", get_top!(then_expr, config)),
+        ];
+        let mut rest = ternary_lines(else_expr, config).into_iter();
+        lines.push(format!(": {}", rest.next().unwrap()));
+        lines.extend(rest);
+        lines
+    } else {
+        vec![get_top!(expr, config)]
+    }
+}
+
+fn expression_list(items: &[Box<ASTNode>], config: &Config) -> String {
+    items
+        .iter()
+        .map(|e| get_top!(e, config))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn parenthesize(node: &ASTNode, required: bool, config: &Config) -> String {
+    if required {
+        format!("({})", get_top!(node, config))
+    } else {
+        get_top!(node, config)
+    }
+}
+
 impl fmt::Display for ASTNode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.class {
             ASTClass::Identifire(ref s) => {
-                return write!(f, "{}", s);
+                write!(f, "{}", s)
             }
             ASTClass::Number(ref num) => {
-                return write!(f, "{}", num);
+                write!(f, "{}", num)
             }
             ASTClass::String(ref path) => {
-                return write!(f, "\"{}\"", path);
+                write!(f, "\"{}\"", path)
             }
             ASTClass::Operator(ref op) => write!(f, "{}", op),
             ASTClass::UnaryOperator(ref uop) => write!(f, "{}", uop),
@@ -0,0 +1,25 @@
+/*
+ * knobs of the output layout
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    // values of a mem initializer on one line before it wraps
+    pub mem_values_per_line: usize,
+    // a statement longer than this is broken into lines where it can be
+    pub max_width: usize,
+    // #ifdef and the others in a block follow its indent instead of column 0
+    pub indent_directives: bool,
+    // the input is a header file, see ast::header_lines()
+    pub header: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            mem_values_per_line: 8,
+            max_width: 80,
+            indent_directives: false,
+            header: false,
+        }
+    }
+}
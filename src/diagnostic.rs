@@ -0,0 +1,145 @@
+use parser::*;
+use token::*;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/*
+ * a message which points at a location of the source, rendered like
+ *
+ *  error: expected `;` after `input` declaration, found `}`
+ *   --> hello.nsl:3:1
+ *    |
+ *  3 | }
+ *    | ^ expected `;`
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    pub width: usize,
+    pub label: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: String, line: usize, column: usize, width: usize) -> Diagnostic {
+        Diagnostic {
+            message,
+            line,
+            column,
+            width,
+            label: None,
+        }
+    }
+
+    pub fn from_parse_error(e: &ParseError) -> Diagnostic {
+        let found = describe(&e.token.class);
+        // the whole of a bad literal is underlined, the end of file by one `^`
+        let span = e.span();
+        let width = (span.end - span.start).max(1);
+        let mut d = Diagnostic::new(String::new(), e.line, e.column, width);
+        d.message = match e.kind {
+            ParseErrorKind::Lex(ref le) => format!("{}", le),
+            ParseErrorKind::UnexpectedToken if e.expected.is_empty() => {
+                format!("unexpected {}", found)
+            }
+            ParseErrorKind::UnexpectedToken => {
+                let expected = expected_list(&e.expected);
+                d.label = Some(format!("expected {}", expected));
+                format!("expected {}, found {}", expected, found)
+            }
+        };
+        if let Some(ref c) = e.context {
+            d.message = if e.expected == [TokenKind::Symbol(Symbol::Semicolon)] {
+                format!("expected `;` after {}, found {}", c, found)
+            } else {
+                format!("{} in {}", d.message, c)
+            };
+        }
+        d
+    }
+
+    pub fn render(&self, file_name: &str, source: &str, color: bool) -> String {
+        let paint = |style: &str, s: &str| {
+            if color {
+                format!("{}{}{}", style, s, RESET)
+            } else {
+                s.to_string()
+            }
+        };
+
+        let line_no = format!("{}", self.line);
+        let gutter = " ".repeat(line_no.len());
+        let mut out = format!(
+            "{}{}\n",
+            paint(RED, "error"),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+        out.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            paint(BLUE, "-->"),
+            file_name,
+            self.line,
+            self.column
+        ));
+
+        let src_line = match source.lines().nth(self.line.saturating_sub(1)) {
+            Some(l) => l,
+            None => return out,
+        };
+        let bar = paint(BLUE, "|");
+        out.push_str(&format!("{} {}\n", gutter, bar));
+        out.push_str(&format!("{} {} {}\n", paint(BLUE, &line_no), bar, src_line));
+
+        // keep tabs so that the marker lines up with the source line
+        let indent: String = src_line
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let mut marker = "^".repeat(self.width.max(1));
+        if let Some(ref label) = self.label {
+            marker = format!("{} {}", marker, label);
+        }
+        out.push_str(&format!(
+            "{} {} {}{}\n",
+            gutter,
+            bar,
+            indent,
+            paint(RED, &marker)
+        ));
+        out
+    }
+}
+
+fn describe(class: &TokenClass) -> String {
+    match class {
+        TokenClass::Identifire(ref id) => format!("identifier `{}`", id),
+        TokenClass::Number(ref num) => format!("number `{}`", num),
+        TokenClass::String(ref s) => format!("string \"{}\"", s),
+        TokenClass::Symbol(ref s) => format!("`{}`", s),
+        TokenClass::Operator(ref op) => format!("`{}`", op),
+        TokenClass::UnaryOperator(ref op) => format!("`{}`", op),
+        TokenClass::Macro(_) => "macro directive".to_string(),
+        TokenClass::CPPStyleComment(_) | TokenClass::CStyleComment(_) => {
+            "comment".to_string()
+        }
+        TokenClass::Text(_) => "text".to_string(),
+        TokenClass::Newline => "newline".to_string(),
+        TokenClass::EndOfProgram => "end of file".to_string(),
+        TokenClass::Error(ref e) => format!("{}", e),
+    }
+}
+
+fn expected_list(kinds: &[TokenKind]) -> String {
+    let names: Vec<String> = kinds.iter().map(|k| format!("{}", k)).collect();
+    match names.len() {
+        1 => names[0].clone(),
+        2 => format!("{} or {}", names[0], names[1]),
+        n => format!("one of {}, or {}", names[..n - 1].join(", "), names[n - 1]),
+    }
+}
@@ -1,37 +1,111 @@
+use std::collections::LinkedList;
+use std::error;
+use std::fmt;
 use std::io::{self, Write};
 
 use ast::*;
+use config::Config;
 use parser::*;
 
+#[derive(Debug)]
+pub enum GenerateError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl From<io::Error> for GenerateError {
+    fn from(e: io::Error) -> GenerateError {
+        GenerateError::Io(e)
+    }
+}
+
+impl From<ParseError> for GenerateError {
+    fn from(e: ParseError) -> GenerateError {
+        GenerateError::Parse(e)
+    }
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenerateError::Io(ref e) => write!(f, "{}", e),
+            GenerateError::Parse(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for GenerateError {}
+
 pub struct Generator<'a, 'b> {
     parser: Parser<'a>,
-    writer: &'b mut Write,
+    writer: &'b mut dyn Write,
+    config: Config,
 }
 
 impl<'a, 'b> Generator<'a, 'b> {
-    pub fn new(parser: Parser<'a>, writer: &'b mut Write) -> Generator<'a, 'b> {
+    pub fn new(parser: Parser<'a>, writer: &'b mut dyn Write) -> Generator<'a, 'b> {
+        Generator::with_config(parser, writer, Config::default())
+    }
+
+    pub fn with_config(
+        parser: Parser<'a>,
+        writer: &'b mut dyn Write,
+        config: Config,
+    ) -> Generator<'a, 'b> {
         Generator {
-            parser: parser,
-            writer: writer,
+            parser,
+            writer,
+            config,
         }
     }
 
-    pub fn output_node(&mut self) -> Result<(), io::Error> {
+    pub fn output_node(&mut self) -> Result<(), GenerateError> {
+        if self.config.header {
+            return self.output_header();
+        }
+        // consecutive #define are held back to be aligned together
+        let mut defines = vec![];
         loop {
-            let ast = self.parser.next_ast();
+            let ast = self.parser.next_ast()?;
+            if is_define(&ast) {
+                defines.push(ast);
+                continue;
+            }
+            if !defines.is_empty() {
+                let lines = aligned_defines(&defines, &self.config);
+                self.write_lines(lines)?;
+                defines.clear();
+            }
             match ast.class {
                 ASTClass::EndOfProgram => {
                     return Ok(());
                 }
                 _ => {
-                    let mut block_content = ast.generate();
-                    let head = block_content.pop_front().unwrap();
-                    let result = block_content
-                        .iter()
-                        .fold(head, |prev, s| format!("{}\n{}", prev, s));
-                    try!(self.writer.write(format!("{}\n", result).as_bytes()));
+                    let lines = ast.generate_with(&self.config);
+                    self.write_lines(lines)?;
                 }
             }
         }
     }
+
+    fn write_lines(&mut self, lines: LinkedList<String>) -> Result<(), GenerateError> {
+        for line in lines {
+            self.writer.write_all(format!("{}\n", line).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    // a header is laid out as a whole, the #define are aligned across nodes
+    fn output_header(&mut self) -> Result<(), GenerateError> {
+        let mut nodes = vec![];
+        loop {
+            let ast = self.parser.next_ast()?;
+            if ast.class == ASTClass::EndOfProgram {
+                break;
+            }
+            nodes.push(ast);
+        }
+        let lines = header_lines(&nodes, &self.config);
+        self.write_lines(lines)
+    }
 }
@@ -14,10 +14,21 @@ struct CommentResult(String, CommentState);
 pub struct Lexer<'a> {
     pub line: usize,
     pub current_position: usize,
-    reader: &'a mut BufRead,
+    // byte offset and column of the next character
+    offset: usize,
+    column: usize,
+    // where the token being generated starts
+    token_start: Span,
+    // end of the last token returned by next()
+    last_end: usize,
+    reader: &'a mut dyn BufRead,
     line_buffer: String,
     iter: Peekable<IntoIter<char>>,
     next_token: Token,
+    // the last token was `#`, so a word is read as a directive
+    directive: bool,
+    // the last token was `error` of #error, the rest of the line is its message
+    raw_line: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -27,7 +38,11 @@ impl<'a> Lexer<'a> {
         let mut lex = Lexer {
             line: init_line,
             current_position: init_position,
-            reader: reader,
+            offset: 0,
+            column: 1,
+            token_start: Span::new(0, 0, init_line, 1),
+            last_end: 0,
+            reader,
             line_buffer: "".to_string(),
             iter: ""
                 .to_string()
@@ -36,6 +51,8 @@ impl<'a> Lexer<'a> {
                 .into_iter()
                 .peekable(),
             next_token: Token::from((TokenClass::Newline, init_line, init_position)),
+            directive: false,
+            raw_line: false,
         };
         lex.next(false);
         lex
@@ -56,11 +73,19 @@ impl<'a> Lexer<'a> {
         let t = self.next_token.clone();
         if TokenClass::Newline != t.class {
             self.current_position += 1;
+            self.last_end = t.span.end;
         }
         self.next_token = self.generate_token();
         t
     }
 
+    /*
+     * byte offset where the last token returned by next() ends
+     */
+    pub fn last_end(&self) -> usize {
+        self.last_end
+    }
+
     fn skip_nl(&mut self) {
         while self.next_token.class == TokenClass::Newline {
             self.next_token = self.generate_token();
@@ -71,7 +96,7 @@ impl<'a> Lexer<'a> {
      * Some(EndOfProgram) or None
      */
     fn supply_buffer(&mut self) -> Option<Token> {
-        if self.iter.peek() == None {
+        if self.iter.peek().is_none() {
             let mut buf = Vec::<u8>::new();
             match self.reader.read_until(b'\n', &mut buf) {
                 Ok(size) => {
@@ -97,24 +122,60 @@ impl<'a> Lexer<'a> {
         None
     }
 
+    fn bump(&mut self) -> Option<char> {
+        let c = self.iter.next();
+        if let Some(c) = c {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        c
+    }
+
     fn generate_token(&mut self) -> Token {
+        let mut t = self.scan_token();
+        self.directive = t.class == TokenClass::Symbol(Symbol::Sharp);
+        self.raw_line = t.class == TokenClass::Macro(Macro::Error);
+        // a block comment may end on a later line than it starts
+        t.line = self.token_start.line;
+        t.span = Span::new(
+            self.token_start.start,
+            self.offset,
+            self.token_start.line,
+            self.token_start.column,
+        );
+        t
+    }
+
+    fn scan_token(&mut self) -> Token {
+        if self.raw_line {
+            return self.get_rest_of_line();
+        }
         loop {
+            self.token_start =
+                Span::new(self.offset, self.offset, self.line, self.column);
             if let Some(t) = self.supply_buffer() {
                 return t;
             }
             while let Some(&c) = self.iter.peek() {
+                self.token_start =
+                    Span::new(self.offset, self.offset, self.line, self.column);
                 match c {
-                    'a'...'z' | 'A'...'Z' | '_' => {
+                    'a'..='z' | 'A'..='Z' | '_' => {
                         let t = self.get_token_from_char();
                         return Token::from((t, self.line, self.current_position));
                     }
                     // TODO
-                    '0'...'9' => {
+                    '0'..='9' => {
                         let t = self.get_number_token();
                         return Token::from((t, self.line, self.current_position));
                     }
                     '{' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::OpeningBrace,
                             self.line,
@@ -122,7 +183,7 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     '}' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::ClosingBrace,
                             self.line,
@@ -130,7 +191,7 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     '(' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::LeftParen,
                             self.line,
@@ -138,7 +199,7 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     ')' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::RightParen,
                             self.line,
@@ -146,7 +207,7 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     '[' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::LeftSquareBracket,
                             self.line,
@@ -154,7 +215,7 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     ']' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::RightSquareBracket,
                             self.line,
@@ -162,7 +223,7 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     ';' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::Semicolon,
                             self.line,
@@ -170,9 +231,9 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     ':' => {
-                        self.iter.next();
+                        self.bump();
                         if let Some('=') = self.iter.peek() {
-                            self.iter.next();
+                            self.bump();
                             return Token::from((
                                 Symbol::RegAssign,
                                 self.line,
@@ -185,8 +246,16 @@ impl<'a> Lexer<'a> {
                             self.current_position,
                         ));
                     }
+                    '?' => {
+                        self.bump();
+                        return Token::from((
+                            Symbol::Question,
+                            self.line,
+                            self.current_position,
+                        ));
+                    }
                     ',' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::Comma,
                             self.line,
@@ -194,7 +263,7 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     '.' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::Dot,
                             self.line,
@@ -203,7 +272,7 @@ impl<'a> Lexer<'a> {
                     }
 
                     '#' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::Sharp,
                             self.line,
@@ -211,7 +280,7 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     '*' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Operator::Asterisk,
                             self.line,
@@ -219,10 +288,10 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     '+' => {
-                        self.iter.next();
+                        self.bump();
                         if let Some(&c_next) = self.iter.peek() {
                             if c_next == '+' {
-                                self.iter.next();
+                                self.bump();
                                 return Token::from((
                                     UnaryOperator::Increment,
                                     self.line,
@@ -237,10 +306,10 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     '-' => {
-                        self.iter.next();
+                        self.bump();
                         if let Some(&c_next) = self.iter.peek() {
                             if c_next == '-' {
-                                self.iter.next();
+                                self.bump();
                                 return Token::from((
                                     UnaryOperator::Decrement,
                                     self.line,
@@ -255,10 +324,10 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     '|' => {
-                        self.iter.next();
+                        self.bump();
                         if let Some(&cc) = self.iter.peek() {
                             if cc == '|' {
-                                self.iter.next();
+                                self.bump();
                                 return Token::from((
                                     Operator::LogicOr,
                                     self.line,
@@ -273,19 +342,27 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     '^' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Operator::Hat,
                             self.line,
                             self.current_position,
                         ));
                     }
+                    '~' => {
+                        self.bump();
+                        return Token::from((
+                            UnaryOperator::Tilde,
+                            self.line,
+                            self.current_position,
+                        ));
+                    }
                     '!' => {
-                        self.iter.next();
+                        self.bump();
                         if let Some(&cc) = self.iter.peek() {
                             match cc {
                                 '=' => {
-                                    self.iter.next();
+                                    self.bump();
                                     return Token::from((
                                         Operator::NotEqual,
                                         self.line,
@@ -303,7 +380,7 @@ impl<'a> Lexer<'a> {
                         }
                     }
                     '\'' => {
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             Symbol::SingleQuote,
                             self.line,
@@ -311,11 +388,11 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     '=' => {
-                        self.iter.next();
+                        self.bump();
                         if let Some(&equal) = self.iter.peek() {
                             match equal {
                                 '=' => {
-                                    self.iter.next();
+                                    self.bump();
                                     return Token::from((
                                         Operator::Equal,
                                         self.line,
@@ -333,11 +410,11 @@ impl<'a> Lexer<'a> {
                         }
                     }
                     '>' => {
-                        self.iter.next();
+                        self.bump();
                         if let Some(&eq) = self.iter.peek() {
                             match eq {
                                 '=' => {
-                                    self.iter.next();
+                                    self.bump();
                                     return Token::from((
                                         Operator::GreaterEq,
                                         self.line,
@@ -345,7 +422,7 @@ impl<'a> Lexer<'a> {
                                     ));
                                 }
                                 '>' => {
-                                    self.iter.next();
+                                    self.bump();
                                     return Token::from((
                                         Operator::ShiftRight,
                                         self.line,
@@ -363,11 +440,11 @@ impl<'a> Lexer<'a> {
                         }
                     }
                     '<' => {
-                        self.iter.next();
+                        self.bump();
                         if let Some(&eq) = self.iter.peek() {
                             match eq {
                                 '=' => {
-                                    self.iter.next();
+                                    self.bump();
                                     return Token::from((
                                         Operator::LessEq,
                                         self.line,
@@ -375,7 +452,7 @@ impl<'a> Lexer<'a> {
                                     ));
                                 }
                                 '<' => {
-                                    self.iter.next();
+                                    self.bump();
                                     return Token::from((
                                         Operator::ShiftLeft,
                                         self.line,
@@ -393,11 +470,11 @@ impl<'a> Lexer<'a> {
                         }
                     }
                     '&' => {
-                        self.iter.next();
+                        self.bump();
                         if let Some(&and) = self.iter.peek() {
                             match and {
                                 '&' => {
-                                    self.iter.next();
+                                    self.bump();
                                     return Token::from((
                                         Operator::LogicAnd,
                                         self.line,
@@ -415,12 +492,12 @@ impl<'a> Lexer<'a> {
                         }
                     }
                     '/' => {
-                        self.iter.next();
+                        self.bump();
                         if let Some(&slash) = self.iter.peek() {
                             match slash {
                                 // single line comment
                                 '/' => {
-                                    self.iter.next();
+                                    self.bump();
                                     let comment = self.get_string_until_newline();
                                     return Token::from((
                                         TokenClass::CPPStyleComment(comment),
@@ -430,11 +507,18 @@ impl<'a> Lexer<'a> {
                                 }
                                 // multi-line comment
                                 '*' => {
-                                    self.iter.next();
-                                    let comment_list =
-                                        self.get_string_for_multiline_comment();
+                                    self.bump();
+                                    let class =
+                                        match self.get_string_for_multiline_comment() {
+                                            Some(comment_list) => {
+                                                TokenClass::CStyleComment(comment_list)
+                                            }
+                                            None => TokenClass::Error(
+                                                LexError::UnterminatedComment,
+                                            ),
+                                        };
                                     return Token::from((
-                                        TokenClass::CStyleComment(comment_list),
+                                        class,
                                         self.line,
                                         self.current_position,
                                     ));
@@ -447,22 +531,29 @@ impl<'a> Lexer<'a> {
                                     ))
                                 }
                             }
-                        } else {
-                            panic!("panic");
                         }
+                        return Token::from((
+                            Operator::Slash,
+                            self.line,
+                            self.current_position,
+                        ));
                     }
                     '"' => {
-                        self.iter.next();
+                        self.bump();
                         let mut name = String::new();
                         loop {
-                            if let Some(nc) = self.iter.next() {
+                            if let Some(nc) = self.bump() {
                                 if nc == '"' {
                                     break;
                                 } else {
-                                    name.push_str(&nc.to_string());
+                                    name.push(nc);
                                 }
                             } else {
-                                panic!("error");
+                                return Token::from((
+                                    TokenClass::Error(LexError::UnterminatedString),
+                                    self.line,
+                                    self.current_position,
+                                ));
                             }
                         }
                         return Token::from((
@@ -473,8 +564,7 @@ impl<'a> Lexer<'a> {
                     }
                     '\n' => {
                         let before_line = self.line;
-                        self.line += 1;
-                        self.iter.next();
+                        self.bump();
                         return Token::from((
                             TokenClass::Newline,
                             before_line,
@@ -482,26 +572,53 @@ impl<'a> Lexer<'a> {
                         ));
                     }
                     ' ' | '\t' => {
-                        self.iter.next();
+                        self.bump();
+                    }
+                    // a line ending with `\` goes on to the next line, e.g. a long #define
+                    '\\' if self.continues_line() => {
+                        self.bump();
+                        self.bump();
                     }
                     _ => {
-                        panic!("invalid input {}", c);
+                        self.bump();
+                        return Token::from((
+                            TokenClass::Error(LexError::InvalidCharacter(c)),
+                            self.line,
+                            self.current_position,
+                        ));
                     }
                 }
             }
         }
     }
 
+    // `\` is the last character of the line
+    fn continues_line(&self) -> bool {
+        let mut rest = self.iter.clone();
+        rest.next();
+        rest.next() == Some('\n')
+    }
+
     fn get_token_from_char(&mut self) -> TokenClass {
         let mut word = String::new();
         while let Some(&c_next) = self.iter.peek() {
             if c_next.is_alphanumeric() | (c_next == '_') {
-                word.push_str(&c_next.to_string());
-                self.iter.next();
+                word.push(c_next);
+                self.bump();
             } else {
                 break;
             }
         }
+        // these are keywords of NSL too, e.g. `if` and `else`
+        if self.directive {
+            match word.as_str() {
+                "if" => return TokenClass::Macro(Macro::If),
+                "elif" => return TokenClass::Macro(Macro::Elif),
+                "else" => return TokenClass::Macro(Macro::Else),
+                "error" => return TokenClass::Macro(Macro::Error),
+                _ => {}
+            }
+        }
         match word.as_str() {
             "declare" => TokenClass::Symbol(Symbol::Declare),
             "module" => TokenClass::Symbol(Symbol::Module),
@@ -510,6 +627,8 @@ impl<'a> Lexer<'a> {
             "inout" => TokenClass::Symbol(Symbol::InOut),
             "func_in" => TokenClass::Symbol(Symbol::FuncIn),
             "func_out" => TokenClass::Symbol(Symbol::FuncOut),
+            "param_int" => TokenClass::Symbol(Symbol::ParamInt),
+            "param_str" => TokenClass::Symbol(Symbol::ParamStr),
             "func_self" => TokenClass::Symbol(Symbol::FuncSelf),
             "include" => TokenClass::Macro(Macro::Include),
             "define" => TokenClass::Macro(Macro::Define),
@@ -521,6 +640,7 @@ impl<'a> Lexer<'a> {
             "reg" => TokenClass::Symbol(Symbol::Reg),
             "proc_name" => TokenClass::Symbol(Symbol::ProcName),
             "state_name" => TokenClass::Symbol(Symbol::StateName),
+            "label_name" => TokenClass::Symbol(Symbol::LabelName),
             "mem" => TokenClass::Symbol(Symbol::Mem),
             "func" => TokenClass::Symbol(Symbol::Func),
             "return" => TokenClass::Symbol(Symbol::Return),
@@ -541,25 +661,49 @@ impl<'a> Lexer<'a> {
             "variable" => TokenClass::Symbol(Symbol::Variable),
             "struct" => TokenClass::Symbol(Symbol::Struct),
             "simulation" => TokenClass::Symbol(Symbol::Simulation),
+            "interface" => TokenClass::Symbol(Symbol::Interface),
             //TODO
             _ => TokenClass::Identifire(word),
         }
     }
 
+    /*
+     * free-form text until the end of the line, e.g. the message of #error.
+     * the text is not made into tokens, so `$` or `it's` are no errors.
+     */
+    fn get_rest_of_line(&mut self) -> Token {
+        while let Some(&c) = self.iter.peek() {
+            if c != ' ' && c != '\t' {
+                break;
+            }
+            self.bump();
+        }
+        self.token_start = Span::new(self.offset, self.offset, self.line, self.column);
+        let text = self.get_string_until_newline();
+        Token::from((
+            TokenClass::Text(text.trim_end().to_string()),
+            self.line,
+            self.current_position,
+        ))
+    }
+
     fn get_string_until_newline(&mut self) -> String {
         let mut word = String::new();
         while let Some(&c_next) = self.iter.peek() {
             if c_next == '\n' {
                 break;
             } else {
-                word.push_str(&c_next.to_string());
-                self.iter.next();
+                word.push(c_next);
+                self.bump();
             }
         }
         word
     }
 
-    fn get_string_for_multiline_comment(&mut self) -> Vec<String> {
+    /*
+     * None when the comment is not closed until EOF
+     */
+    fn get_string_for_multiline_comment(&mut self) -> Option<Vec<String>> {
         let mut result: Vec<String> = Vec::new();
 
         while let Some(r) = self.get_comment_oneline() {
@@ -567,50 +711,47 @@ impl<'a> Lexer<'a> {
             result.push(r.0);
             match r.1 {
                 CommentState::Finished => {
-                    return result;
+                    return Some(result);
                 }
                 CommentState::Continue => {}
             }
         }
-        panic!("comment is not closed but got EOF");
+        None
     }
 
+    /*
+     * the whole literal is taken first and checked by Number::parse,
+     * so that `4'b102` is reported as one bad number, not `4'b10` and `2`.
+     * `32'(a)` is a zero extension, its `'` is left for the next token.
+     */
     fn get_number_token(&mut self) -> TokenClass {
-        let mut number = self.get_number();
-        if let Some(&c) = self.iter.peek() {
-            match c {
-                '\'' => {
-                    self.iter.next();
-                    let id = self.get_token_from_char();
-                    let t = Token::from((id, self.line, self.current_position));
-                    number.push_str(&format!("'{}", t));
-                    return TokenClass::Number(number);
-                }
-                'x' | 'b' => {
-                    self.iter.next();
-                    number.push_str(&format!("{}{}", c, self.get_number()));
-                    return TokenClass::Number(number);
-                }
-                _ => {
-                    return TokenClass::Number(number);
-                }
-            }
+        let mut spelling = self.get_number();
+        let mut ahead = self.iter.clone();
+        if ahead.next() == Some('\'')
+            && ahead.peek().is_some_and(|c| c.is_ascii_alphanumeric())
+        {
+            self.bump();
+            spelling.push('\'');
+            spelling.push_str(&self.get_number());
+        }
+        match Number::parse(&spelling) {
+            Ok(number) => TokenClass::Number(number),
+            Err(e) => TokenClass::Error(e),
         }
-        panic!("cannot get character");
     }
 
     fn get_comment_oneline(&mut self) -> Option<CommentResult> {
         let mut word = String::new();
         let mut astarisc_flag = false;
         while let Some(&c_next) = self.iter.peek() {
-            self.iter.next();
+            self.bump();
             match c_next {
                 '\n' => {
                     //word.push_str(&c_next.to_string());
                     return Some(CommentResult(word, CommentState::Continue));
                 }
                 '*' => {
-                    word.push_str(&c_next.to_string());
+                    word.push(c_next);
                     astarisc_flag = true;
                 }
                 '/' => {
@@ -618,23 +759,23 @@ impl<'a> Lexer<'a> {
                         word.pop();
                         return Some(CommentResult(word, CommentState::Finished));
                     }
-                    word.push_str(&c_next.to_string());
+                    word.push(c_next);
                 }
                 _ => {
                     astarisc_flag = false;
-                    word.push_str(&c_next.to_string());
+                    word.push(c_next);
                 }
             }
         }
-        return Some(CommentResult(word, CommentState::Finished));
+        None
     }
 
     fn get_number(&mut self) -> String {
         let mut number = String::new();
         while let Some(&c_next) = self.iter.peek() {
-            if c_next.is_digit(16) | (c_next == '_') {
-                number.push_str(&c_next.to_string());
-                self.iter.next();
+            if c_next.is_ascii_alphanumeric() | (c_next == '_') {
+                number.push(c_next);
+                self.bump();
             } else {
                 break;
             }
@@ -1,5 +1,8 @@
 pub mod ast;
+pub mod config;
+pub mod diagnostic;
 pub mod generator;
 pub mod lexer;
 pub mod parser;
+pub mod preprocess;
 pub mod token;
@@ -1,24 +1,23 @@
 extern crate backtrace;
 extern crate getopts;
+extern crate nslfmt;
 
 use backtrace::Backtrace;
 use getopts::Options;
 
 use std::env;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{IsTerminal, Read};
 use std::panic;
+use std::path::Path;
 use std::process;
 
-mod ast;
-mod generator;
-mod lexer;
-mod parser;
-mod token;
-
-use generator::Generator;
-use lexer::Lexer;
-use parser::Parser;
+use nslfmt::config::Config;
+use nslfmt::diagnostic::Diagnostic;
+use nslfmt::generator::{GenerateError, Generator};
+use nslfmt::lexer::Lexer;
+use nslfmt::parser::Parser;
+use nslfmt::preprocess::Preprocessor;
 
 fn print_version() {
     let version_info = format!(
@@ -31,7 +30,7 @@ fn print_version() {
 }
 
 fn print_usage(opts: Options) {
-    let brief = format!("Usage: nslfmt FILE [options]");
+    let brief = "Usage: nslfmt FILE [options]".to_string();
     println!("{}", opts.usage(&brief));
 }
 
@@ -41,10 +40,45 @@ fn main() {
     opts.optflag("h", "help", "print this help menu");
     opts.optflag("v", "version", "print version");
     opts.optflag("d", "debug", "print debug info");
+    opts.optopt(
+        "",
+        "color",
+        "coloring of error messages: auto, always or never",
+        "WHEN",
+    );
+
+    opts.optopt(
+        "",
+        "mem-values-per-line",
+        "number of values on a line of a mem initializer (default: 8)",
+        "N",
+    );
+    opts.optflag(
+        "",
+        "indent-directives",
+        "indent #ifdef and the others in a block instead of putting them at column 0",
+    );
+    opts.optflag(
+        "",
+        "header",
+        "format the file as a header, implied by the extension .nh or .nsh",
+    );
+    opts.optflag(
+        "",
+        "preprocess",
+        "print the source with the macros expanded instead of formatting it",
+    );
+    opts.optmulti("I", "", "search DIR for the files of #include", "DIR");
+    opts.optopt(
+        "",
+        "max-width",
+        "width where a long expression is broken (default: 80)",
+        "N",
+    );
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(f) => panic!(f.to_string()),
+        Err(f) => panic!("{}", f),
     };
 
     if matches.opt_present("h") {
@@ -63,6 +97,39 @@ fn main() {
         }));
     }
 
+    let color = match matches.opt_str("color").as_deref() {
+        None | Some("auto") => std::io::stderr().is_terminal(),
+        Some("always") => true,
+        Some("never") => false,
+        Some(other) => {
+            eprintln!("invalid value for --color: {}", other);
+            process::exit(-1);
+        }
+    };
+
+    let mut config = Config::default();
+    if let Some(n) = matches.opt_str("mem-values-per-line") {
+        config.mem_values_per_line = match n.parse() {
+            Ok(n) if n > 0 => n,
+            _ => {
+                eprintln!("invalid value for --mem-values-per-line: {}", n);
+                process::exit(-1);
+            }
+        };
+    }
+
+    config.indent_directives = matches.opt_present("indent-directives");
+
+    if let Some(n) = matches.opt_str("max-width") {
+        config.max_width = match n.parse() {
+            Ok(n) if n > 0 => n,
+            _ => {
+                eprintln!("invalid value for --max-width: {}", n);
+                process::exit(-1);
+            }
+        };
+    }
+
     let input_file = if !matches.free.is_empty() {
         matches.free[0].clone()
     } else {
@@ -70,26 +137,79 @@ fn main() {
         process::exit(-1);
     };
 
-    let fd = match File::open(input_file) {
-        Ok(a) => a,
-        Err(e) => {
-            println!("{}", e);
+    let extension = Path::new(&input_file).extension().and_then(|e| e.to_str());
+    config.header = matches.opt_present("header")
+        || extension == Some("nh")
+        || extension == Some("nsh");
+
+    let mut pp = Preprocessor::new();
+    for dir in matches.opt_strs("I") {
+        pp.add_include_path(dir);
+    }
+
+    if matches.opt_present("preprocess") {
+        match pp.process_file(Path::new(&input_file)) {
+            Ok(expanded) => print!("{}", expanded),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(-1);
+            }
+        }
+        return;
+    }
+
+    let mut source = String::new();
+    if let Err(e) =
+        File::open(&input_file).and_then(|mut f| f.read_to_string(&mut source))
+    {
+        println!("{}", e);
+        process::exit(-1);
+    }
+
+    // the names defined in the included headers, a statement made of one of them
+    // is kept as it is. a header which is not found defines nothing.
+    pp.collect_defines(Path::new(&input_file));
+    let defines = pp.defined_names();
+
+    // report every syntax error in the file before formatting anything
+    {
+        let mut b = source.as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+        for name in &defines {
+            p.define(name);
+        }
+        let (_, errors) = p.parse_recovering();
+        if !errors.is_empty() {
+            for e in &errors {
+                let d = Diagnostic::from_parse_error(e);
+                eprintln!("{}", d.render(&input_file, &source, color));
+            }
             process::exit(-1);
         }
-    };
+    }
 
-    let mut b = BufReader::new(fd);
+    let mut b = source.as_bytes();
     let mut l = Lexer::new(&mut b);
 
-    let p = Parser::new(&mut l);
+    let mut p = Parser::new(&mut l);
+    for name in &defines {
+        p.define(name);
+    }
     let mut io = std::io::stdout();
 
     {
-        let mut g = Generator::new(p, &mut io);
+        let mut g = Generator::with_config(p, &mut io, config);
         match g.output_node() {
             Ok(()) => {}
+            Err(GenerateError::Parse(e)) => {
+                let d = Diagnostic::from_parse_error(&e);
+                eprint!("{}", d.render(&input_file, &source, color));
+                process::exit(-1);
+            }
             Err(e) => {
-                panic!("{}", e);
+                eprintln!("{}", e);
+                process::exit(-1);
             }
         }
     }
@@ -1,4 +1,6 @@
-use std::collections::LinkedList;
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
 
 use ast::*;
 use lexer::*;
@@ -6,212 +8,437 @@ use token::*;
 
 pub struct Parser<'a> {
     lexer: &'a mut Lexer<'a>,
-    comments: LinkedList<Box<ASTNode>>,
+    // keep going after a syntax error, see parse_recovering()
+    recovery: bool,
+    errors: Vec<ParseError>,
+    // the `}` of the enclosing block was eaten by a broken statement
+    closed_by_error: bool,
+    // names given by #define, see is_macro_statement()
+    defines: HashSet<String>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    UnexpectedToken,
+    Lex(LexError),
+}
+
+/*
+ * an error reported by the parser.
+ * it holds the offending token, the token kinds which were acceptable
+ * at that point and the location of the token.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub token: Box<Token>,
+    pub expected: Vec<TokenKind>,
+    pub line: usize,
+    pub column: usize,
+    // what the parser was reading, e.g. "`wire` declaration"
+    pub context: Option<String>,
+}
+
+impl ParseError {
+    pub fn new(
+        kind: ParseErrorKind,
+        token: Token,
+        expected: Vec<TokenKind>,
+    ) -> ParseError {
+        let span = token.span;
+        // the lexer reports broken input as a token, so the parser only sees it here
+        let kind = match token.class {
+            TokenClass::Error(ref e) => ParseErrorKind::Lex(e.clone()),
+            _ => kind,
+        };
+        ParseError {
+            kind,
+            token: Box::new(token),
+            expected,
+            line: span.line,
+            column: span.column,
+            context: None,
+        }
+    }
+
+    // where the offending token is
+    pub fn span(&self) -> Span {
+        self.token.span
+    }
+
+    /*
+     * set the context unless an inner construct has already set it.
+     */
+    pub fn within(mut self, context: Option<String>) -> ParseError {
+        if self.context.is_none() {
+            self.context = context;
+        }
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::Lex(ref e) => {
+                write!(f, "{}:{}: {}", self.line, self.column, e)
+            }
+            ParseErrorKind::UnexpectedToken => {
+                write!(
+                    f,
+                    "{}:{}: unexpected token {:?}",
+                    self.line, self.column, self.token.class
+                )?;
+                if !self.expected.is_empty() {
+                    let expected = self
+                        .expected
+                        .iter()
+                        .map(|k| format!("{}", k))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    write!(f, ", expected {}", expected)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
 #[macro_export]
 macro_rules! create_node {
-    ($n:expr, $p:ident) => {
-        Box::new(ASTNode::new($n, $p))
+    ($n:expr) => {
+        Box::new(ASTNode::new($n, $crate::token::Span::default()))
     };
-}
-
-macro_rules! not_implemented {
-    () => {
-        panic!("not implemented yet. at line {} in {}.", line!(), file!())
+    ($n:expr, $s:expr) => {
+        Box::new(ASTNode::new($n, $s))
     };
 }
 
 macro_rules! unexpected_token {
-    ($n:expr) => {
-        panic!("unexpected_token {:?}", $n)
+    ($t:expr) => {
+        return Err(ParseError::new(ParseErrorKind::UnexpectedToken, $t, vec![]))
+    };
+    ($t:expr, $($k:expr),+) => {
+        return Err(ParseError::new(
+            ParseErrorKind::UnexpectedToken,
+            $t,
+            vec![$($k),+],
+        ))
     };
 }
 
 impl<'a> Parser<'a> {
     pub fn new(lexer: &'a mut Lexer<'a>) -> Parser<'a> {
         Parser {
-            lexer: lexer,
-            comments: LinkedList::new(),
+            lexer,
+            recovery: false,
+            errors: vec![],
+            closed_by_error: false,
+            defines: HashSet::new(),
         }
     }
 
-    pub fn next_ast(&mut self) -> Box<ASTNode> {
-        let token = self.lexer.next(true);
-        match token.class {
-            TokenClass::Symbol(Symbol::Sharp) => self.macro_ast(),
-            TokenClass::Symbol(Symbol::Declare) => self.declare_ast(),
-            TokenClass::Symbol(Symbol::Module) => self.module_ast(),
-            TokenClass::Symbol(Symbol::Struct) => self.struct_ast(),
-            TokenClass::EndOfProgram => create_node!(ASTClass::EndOfProgram, token.position),
-            _ => {
-                unexpected_token!(token);
-            }
-        }
+    /*
+     * tell the parser a name defined outside of the input, e.g. in an included header.
+     * the #define in the input are gathered while parsing.
+     */
+    pub fn define(&mut self, name: &str) {
+        self.defines.insert(name.to_string());
     }
 
-    fn next_token(&mut self, skip_nl: bool) -> Token {
+    /*
+     * parse the whole input without stopping at the first syntax error.
+     * a broken statement is skipped until `;` or `}` and replaced with
+     * an ASTClass::Error node, so the result is a partial AST and every
+     * error found on the way.
+     */
+    pub fn parse_recovering(&mut self) -> (Vec<Box<ASTNode>>, Vec<ParseError>) {
+        self.recovery = true;
+        let mut nodes = vec![];
         loop {
-            let token = self.lexer.next(skip_nl);
-            match token.class {
-                TokenClass::CPPStyleComment(comment) => {
-                    self.comments
-                        .push_back(create_node!(ASTClass::CPPStyleComment(
-                            comment,
-                            token.position
-                        )));
-                }
-                TokenClass::CStyleComment(comments) => {
-                    not_implemented!();
-                }
-                _ => {
-                    return token;
-                }
+            let ast = match self.next_ast() {
+                Ok(ast) => ast,
+                Err(e) => self.recover(e),
+            };
+            // a `}` eaten at the top level closes nothing
+            self.closed_by_error = false;
+            if let ASTClass::EndOfProgram = ast.class {
+                break;
             }
+            nodes.push(ast);
         }
+        self.recovery = false;
+        (nodes, self.errors.drain(..).collect())
+    }
+
+    pub fn next_ast(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let token = self.lexer.next(true);
+        let node = match token.class {
+            TokenClass::Symbol(Symbol::Sharp) => self.macro_ast()?,
+            TokenClass::Symbol(Symbol::Declare) => self.declare_ast()?,
+            TokenClass::Symbol(Symbol::Module) => self.module_ast()?,
+            TokenClass::Symbol(Symbol::Struct) => self.struct_ast()?,
+            TokenClass::CPPStyleComment(comment) => {
+                create_node!(ASTClass::CPPStyleComment(comment))
+            }
+            TokenClass::CStyleComment(list) => {
+                create_node!(ASTClass::CStyleComment(list))
+            }
+            TokenClass::EndOfProgram => create_node!(ASTClass::EndOfProgram),
+            _ => {
+                unexpected_token!(
+                    token,
+                    TokenKind::Symbol(Symbol::Sharp),
+                    TokenKind::Symbol(Symbol::Declare),
+                    TokenKind::Symbol(Symbol::Module),
+                    TokenKind::Symbol(Symbol::Struct)
+                );
+            }
+        };
+        Ok(self.spanned(node, token.span))
     }
 
-    fn macro_ast(&mut self) -> Box<ASTNode> {
+    fn macro_ast(&mut self) -> Result<Box<ASTNode>, ParseError> {
         let macro_kind_token = self.lexer.next(true);
         match macro_kind_token.class {
-            TokenClass::Macro(Macro::Include) => {
-                create_node!(ASTClass::MacroInclude(self.generate_path_node()), macro_kind_token.token)
-            }
+            TokenClass::Macro(Macro::Include) => Ok(create_node!(
+                ASTClass::MacroInclude(self.generate_path_node()?)
+            )),
             TokenClass::Macro(Macro::Undef) => {
-                let id = self.generate_id_node();
-                create_node!(ASTClass::MacroUndef(id), macro_kind_token.token)
+                let id = self.generate_id_node()?;
+                Ok(create_node!(ASTClass::MacroUndef(id)))
             }
             TokenClass::Macro(Macro::Ifdef) => {
-                let id = self.generate_id_node();
-                create_node!(ASTClass::MacroIfdef(id), macro_kind_token.token)
+                let id = self.generate_id_node()?;
+                Ok(create_node!(ASTClass::MacroIfdef(id)))
             }
             TokenClass::Macro(Macro::Ifndef) => {
-                let id = self.generate_id_node();
-                create_node!(ASTClass::MacroIfndef(id), macro_kind_token.token)
+                let id = self.generate_id_node()?;
+                Ok(create_node!(ASTClass::MacroIfndef(id)))
+            }
+            TokenClass::Macro(Macro::Endif) => Ok(create_node!(ASTClass::MacroEndif)),
+            TokenClass::Macro(Macro::Else) => Ok(create_node!(ASTClass::MacroElse)),
+            TokenClass::Macro(Macro::If) => {
+                let cond = self.directive_text()?;
+                Ok(create_node!(ASTClass::MacroIf(cond)))
+            }
+            TokenClass::Macro(Macro::Elif) => {
+                let cond = self.directive_text()?;
+                Ok(create_node!(ASTClass::MacroElif(cond)))
+            }
+            TokenClass::Macro(Macro::Error) => {
+                // the lexer gives the message as it is
+                let t = self.lexer.next(false);
+                match t.class {
+                    TokenClass::Text(message) => {
+                        Ok(create_node!(ASTClass::MacroError(message)))
+                    }
+                    _ => {
+                        unexpected_token!(t);
+                    }
+                }
             }
-            TokenClass::Macro(Macro::Endif) => create_node!(ASTClass::MacroEndif, macro_kind_token.token),
-            TokenClass::Symbol(Symbol::Else) => create_node!(ASTClass::MacroElse, macro_kind_token.token),
             TokenClass::Macro(Macro::Define) => {
-                let id = self.generate_id_node();
-                let value = self.generate_string_until_nl();
-                create_node!(ASTClass::MacroDefine(id, value), macro_kind_token.token)
+                let id = self.generate_id_node()?;
+                // kept after #undef as well, the conditionals are not evaluated here
+                self.defines.insert(format!("{}", id));
+                let value = self.macro_value()?;
+                // a trailing comment stays with the define to be kept in its column
+                let comment = match self.lexer.peek(false).class {
+                    TokenClass::CPPStyleComment(_) => {
+                        match self.lexer.next(false).class {
+                            TokenClass::CPPStyleComment(c) => Some(c),
+                            _ => unreachable!(),
+                        }
+                    }
+                    _ => None,
+                };
+                Ok(create_node!(ASTClass::MacroDefine(id, value, comment)))
             }
             _ => {
-                unexpected_token!(macro_kind_token);
+                unexpected_token!(macro_kind_token, TokenKind::Macro);
             }
         }
     }
 
-    fn declare_ast(&mut self) -> Box<ASTNode> {
+    fn declare_ast(&mut self) -> Result<Box<ASTNode>, ParseError> {
         // <identifire>
-        let id_node = self.generate_id_node();
+        let id_node = self.generate_id_node()?;
         let s_token = self.lexer.peek(true);
-        let is_simulation = if s_token.class == TokenClass::Symbol(Symbol::Simulation) {
-            self.lexer.next(true);
-            true
-        } else {
-            false
-        };
-        self.check_opening_brace();
+        match s_token.class {
+            TokenClass::Symbol(Symbol::Interface) => {
+                self.lexer.next(true);
+                let block = self.declare_block_ast()?;
+                Ok(create_node!(ASTClass::Interface(id_node, block)))
+            }
+            TokenClass::Symbol(Symbol::Simulation) => {
+                self.lexer.next(true);
+                let block = self.declare_block_ast()?;
+                Ok(create_node!(ASTClass::Declare(id_node, block, true)))
+            }
+            _ => {
+                let block = self.declare_block_ast()?;
+                Ok(create_node!(ASTClass::Declare(id_node, block, false)))
+            }
+        }
+    }
+
+    fn declare_block_ast(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let block_start = self.lexer.peek(true).span;
+        self.check_opening_brace()?;
         let mut contents_in_block = vec![];
         loop {
-            {
-                let next = self.lexer.peek(true);
-                if let TokenClass::Symbol(Symbol::ClosingBrace) = next.class {
+            let next = self.lexer.peek(true);
+            match next.class {
+                TokenClass::Symbol(Symbol::ClosingBrace) => {
                     self.lexer.next(true);
-                    return create_node!(ASTClass::Declare(
-                        id_node,
-                        create_node!(ASTClass::Block(contents_in_block)),
-                        is_simulation,
-                    ));
+                    break;
                 }
+                TokenClass::EndOfProgram => {
+                    unexpected_token!(next, TokenKind::Symbol(Symbol::ClosingBrace));
+                }
+                _ => {}
             }
-            let declare_block = self.declare_block_part_ast();
+            let declare_block = self.declare_block_part_ast()?;
             contents_in_block.push(declare_block);
+            if self.closed_by_error {
+                self.closed_by_error = false;
+                break;
+            }
         }
+        Ok(create_node!(
+            ASTClass::Block(contents_in_block),
+            self.span_from(block_start)
+        ))
     }
 
-    fn module_ast(&mut self) -> Box<ASTNode> {
-        let id_node = self.generate_id_node();
-        create_node!(ASTClass::Module(id_node, self.module_block_ast()))
+    fn module_ast(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let id_node = self.generate_id_node()?;
+        Ok(create_node!(ASTClass::Module(
+            id_node,
+            self.module_block_ast()?
+        )))
     }
 
-    fn struct_ast(&mut self) -> Box<ASTNode> {
-        let id_node = self.generate_id_node();
-        self.check_opening_brace();
+    fn struct_ast(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let id_node = self.generate_id_node()?;
+        self.check_opening_brace()?;
         let mut struct_contents: Vec<(Box<ASTNode>, Option<Box<ASTNode>>)> = vec![];
         loop {
             let t = self.lexer.peek(true);
             match t.class {
                 TokenClass::Symbol(Symbol::ClosingBrace) => {
                     self.lexer.next(true);
-                    self.check_semicolon();
-                    return create_node!(ASTClass::Struct(id_node, struct_contents));
+                    self.check_semicolon()?;
+                    return Ok(create_node!(ASTClass::Struct(id_node, struct_contents)));
                 }
-                TokenClass::Identifire(id) => {
-                    let (member_id, width) = self.get_id_and_width();
+                TokenClass::Identifire(_) => {
+                    let (member_id, width) = self.get_id_and_width()?;
                     struct_contents.push((member_id, width));
                 }
                 _ => {
-                    panic!();
+                    unexpected_token!(
+                        t,
+                        TokenKind::Identifire,
+                        TokenKind::Symbol(Symbol::ClosingBrace)
+                    );
                 }
             }
         }
     }
 
-    fn module_block_ast(&mut self) -> Box<ASTNode> {
-        self.check_opening_brace();
+    fn module_block_ast(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let start = self.lexer.peek(true).span;
+        self.check_opening_brace()?;
         let mut contents_in_block = vec![];
         loop {
-            if let TokenClass::Symbol(Symbol::ClosingBrace) = self.lexer.peek(true).class
-            {
-                self.lexer.next(true);
-                return create_node!(ASTClass::Block(contents_in_block));
+            let next = self.lexer.peek(true);
+            match next.class {
+                TokenClass::Symbol(Symbol::ClosingBrace) => {
+                    self.lexer.next(true);
+                    break;
+                }
+                TokenClass::EndOfProgram => {
+                    unexpected_token!(next, TokenKind::Symbol(Symbol::ClosingBrace));
+                }
+                _ => {}
             }
-            if let Some(block) = self.module_block_part_ast() {
+            if let Some(block) = self.module_block_part_ast()? {
                 contents_in_block.push(block);
             }
+            if self.closed_by_error {
+                self.closed_by_error = false;
+                break;
+            }
         }
+        Ok(create_node!(
+            ASTClass::Block(contents_in_block),
+            self.span_from(start)
+        ))
     }
 
-    fn wire_module_list(&mut self) -> Vec<(Box<ASTNode>, Option<Box<ASTNode>>)> {
+    #[allow(clippy::type_complexity)]
+    fn wire_module_list(
+        &mut self,
+    ) -> Result<Vec<(Box<ASTNode>, Option<Box<ASTNode>>)>, ParseError> {
         let mut def_list = vec![];
         loop {
             let next = self.lexer.next(true);
             match next.class {
                 TokenClass::Symbol(Symbol::Semicolon) => {
-                    return def_list;
+                    return Ok(def_list);
                 }
                 TokenClass::Symbol(Symbol::Comma) => {
                     continue;
                 }
                 TokenClass::Identifire(id) => {
-                    let id_node = create_node!(ASTClass::Identifire(id));
+                    let id_node = create_node!(ASTClass::Identifire(id), next.span);
                     if TokenClass::Symbol(Symbol::LeftSquareBracket)
                         == self.lexer.peek(true).class
                     {
                         self.lexer.next(true);
-                        let width_ast = self.expression_ast();
-                        self.check_right_square_bracket();
+                        let width_ast = self.expression_ast()?;
+                        self.check_right_square_bracket()?;
                         def_list.push((id_node, Some(width_ast)));
                     } else {
                         def_list.push((id_node, None));
                     }
                 }
                 _ => {
-                    unexpected_token!(next);
+                    unexpected_token!(
+                        next,
+                        TokenKind::Identifire,
+                        TokenKind::Symbol(Symbol::Comma),
+                        TokenKind::Symbol(Symbol::Semicolon)
+                    );
                 }
             }
         }
     }
 
-    fn module_block_part_ast(&mut self) -> Option<Box<ASTNode>> {
+    fn module_block_part_ast(&mut self) -> Result<Option<Box<ASTNode>>, ParseError> {
+        let first = self.lexer.peek(true);
+        let context = context_of(&first.class);
+        let node = match self.module_statement_ast() {
+            Ok(node) => node,
+            Err(e) if self.recovery => Some(self.recover(e.within(context))),
+            Err(e) => return Err(e.within(context)),
+        };
+        Ok(node.map(|n| self.spanned(n, first.span)))
+    }
+
+    fn module_statement_ast(&mut self) -> Result<Option<Box<ASTNode>>, ParseError> {
         let t = self.lexer.next(true);
         match t.class {
+            TokenClass::Identifire(ref id) if self.is_macro_statement(id) => {
+                Ok(Some(self.macro_statement(&t)))
+            }
             TokenClass::Symbol(Symbol::Reg) => {
                 let mut reg_list = vec![];
                 loop {
-                    let reg_info = self.reg_definition();
+                    let reg_info = self.reg_definition()?;
                     reg_list.push(reg_info);
                     let token = self.lexer.next(true);
                     match token.class {
@@ -222,51 +449,24 @@ impl<'a> Parser<'a> {
                             continue;
                         }
                         _ => {
-                            unexpected_token!(token);
+                            unexpected_token!(
+                                token,
+                                TokenKind::Symbol(Symbol::Comma),
+                                TokenKind::Symbol(Symbol::Semicolon)
+                            );
                         }
                     }
                 }
-                Some(create_node!(ASTClass::Reg(reg_list)))
+                Ok(Some(create_node!(ASTClass::Reg(reg_list))))
             }
             TokenClass::Symbol(Symbol::Wire) => {
-                let l = self.wire_module_list();
-                Some(create_node!(ASTClass::Wire(l)))
-                /*
-                let mut wire_list = vec![];
-                loop {
-                    let next = self.lexer.next(true);
-                    match next.class {
-                        TokenClass::Symbol(Symbol::Semicolon) => {
-                            return Some(create_node!(ASTClass::Wire(wire_list)));
-                        }
-                        TokenClass::Symbol(Symbol::Comma) => {
-                            continue;
-                        }
-                        TokenClass::Identifire(id) => {
-                            let id_node = create_node!(ASTClass::Identifire(id));
-                            if TokenClass::Symbol(Symbol::LeftSquareBracket)
-                                == self.lexer.peek(true).class
-                            {
-                                self.lexer.next(true);
-                                let width_ast = self.expression_ast();
-                                self.check_right_square_bracket();
-
-                                wire_list.push((id_node, Some(width_ast)));
-                            } else {
-                                wire_list.push((id_node, None));
-                            }
-                        }
-                        _ => {
-                            unexpected_token!(next);
-                        }
-                    }
-                }
-                */
+                let l = self.wire_module_list()?;
+                Ok(Some(create_node!(ASTClass::Wire(l))))
             }
             TokenClass::Symbol(Symbol::Mem) => {
                 let mut defines = vec![];
                 loop {
-                    let mem_info = self.mem_definition();
+                    let mem_info = self.mem_definition()?;
                     defines.push(mem_info);
                     let next = self.lexer.next(true);
                     match next.class {
@@ -277,93 +477,155 @@ impl<'a> Parser<'a> {
                             continue;
                         }
                         _ => {
-                            unexpected_token!(next);
+                            unexpected_token!(
+                                next,
+                                TokenKind::Symbol(Symbol::Comma),
+                                TokenKind::Symbol(Symbol::Semicolon)
+                            );
                         }
                     }
                 }
-                Some(create_node!(ASTClass::Mem(defines)))
+                Ok(Some(create_node!(ASTClass::Mem(defines))))
             }
             // for behavior
             TokenClass::Identifire(id) => {
-                let id_node = create_node!(ASTClass::Identifire(id));
+                let id_node = create_node!(ASTClass::Identifire(id), t.span);
                 let next_t = self.lexer.peek(true);
                 match next_t.class {
                     TokenClass::Symbol(Symbol::Equal) => {
                         self.lexer.next(true);
-                        let expr = self.expression_ast();
-                        self.check_semicolon();
-                        Some(create_node!(ASTClass::Assign(id_node, expr)))
+                        let expr = self.expression_ast()?;
+                        self.check_semicolon()?;
+                        Ok(Some(create_node!(ASTClass::Assign(id_node, expr))))
                     }
                     TokenClass::Symbol(Symbol::RegAssign) => {
                         self.lexer.next(true);
-                        let expr = self.expression_ast();
-                        self.check_semicolon();
-                        Some(create_node!(ASTClass::RegAssign(id_node, expr)))
+                        let expr = self.expression_ast()?;
+                        self.check_semicolon()?;
+                        Ok(Some(create_node!(ASTClass::RegAssign(id_node, expr))))
                     }
                     TokenClass::Symbol(Symbol::LeftParen) => {
                         self.lexer.next(true);
-                        let args = self.generate_args_vec();
-                        self.check_semicolon();
-                        Some(create_node!(ASTClass::FuncCall(id_node, args, None)))
+                        let args = self.generate_args_vec()?;
+                        self.check_semicolon()?;
+                        Ok(Some(create_node!(ASTClass::FuncCall(id_node, args, None))))
+                    }
+                    // a part of a wire or a word of a mem, e.g. r[3:0] = a; m[i] := b;
+                    TokenClass::Symbol(Symbol::LeftSquareBracket) => {
+                        self.lexer.next(true);
+                        let slice = self.bit_slice()?;
+                        self.check_right_square_bracket()?;
+                        let span = self.span_from(id_node.span);
+                        let target =
+                            create_node!(ASTClass::BitslicedExpr(id_node, slice), span);
+                        Ok(Some(self.assignment_ast(target)?))
+                    }
+                    // a label site, e.g. `loop_top:`
+                    TokenClass::Symbol(Symbol::Colon) => {
+                        self.lexer.next(true);
+                        Ok(Some(create_node!(ASTClass::Label(id_node))))
                     }
-                    TokenClass::UnaryOperator(op) => {
+                    TokenClass::UnaryOperator(op) if op.is_postfix() => {
                         self.lexer.next(true);
-                        self.check_semicolon();
-                        Some(create_node!(ASTClass::UnaryOperation(
+                        self.check_semicolon()?;
+                        Ok(Some(create_node!(ASTClass::UnaryOperation(
                             id_node,
-                            create_node!(ASTClass::UnaryOperator(op))
-                        )))
+                            create_node!(ASTClass::UnaryOperator(op), next_t.span)
+                        ))))
                     }
                     TokenClass::Symbol(Symbol::Dot) => {
                         self.lexer.next(true);
-                        let next_id = self.generate_id_node();
+                        let next_id = self.generate_id_node()?;
                         let n_token = self.lexer.peek(true);
                         match n_token.class {
                             TokenClass::Symbol(Symbol::LeftParen) => {
-                                self.check_left_paren();
-                                let args = self.generate_args_vec();
-                                self.check_semicolon();
-                                Some(create_node!(ASTClass::FuncCall(
+                                self.check_left_paren()?;
+                                let args = self.generate_args_vec()?;
+                                self.check_semicolon()?;
+                                Ok(Some(create_node!(ASTClass::FuncCall(
                                     id_node,
                                     args,
                                     Some(next_id)
-                                )))
+                                ))))
                             }
-                            TokenClass::Symbol(Symbol::Equal) => {
-                                self.lexer.next(true);
-                                let right = self.expression_ast();
-                                self.check_semicolon();
-                                Some(create_node!(ASTClass::Assign(
-                                    create_node!(ASTClass::ModulePort(id_node, next_id)),
-                                    right
-                                )))
+                            TokenClass::Symbol(Symbol::Equal)
+                            | TokenClass::Symbol(Symbol::RegAssign)
+                            | TokenClass::Symbol(Symbol::Dot) => {
+                                // a port of a submodule or a member of a struct
+                                // e.g. sub.port = a; r.field.lsb := b;
+                                let span = id_node.span.to(&next_id.span);
+                                let mut target = create_node!(
+                                    ASTClass::ModulePort(id_node, next_id),
+                                    span
+                                );
+                                while TokenClass::Symbol(Symbol::Dot)
+                                    == self.lexer.peek(true).class
+                                {
+                                    self.lexer.next(true);
+                                    let field = self.generate_id_node()?;
+                                    let span = target.span.to(&field.span);
+                                    target = create_node!(
+                                        ASTClass::ModulePort(target, field),
+                                        span
+                                    );
+                                }
+                                Ok(Some(self.assignment_ast(target)?))
                             }
                             _ => {
-                                unexpected_token!(n_token);
+                                unexpected_token!(
+                                    n_token,
+                                    TokenKind::Symbol(Symbol::LeftParen),
+                                    TokenKind::Symbol(Symbol::Equal),
+                                    TokenKind::Symbol(Symbol::RegAssign),
+                                    TokenKind::Symbol(Symbol::Dot)
+                                );
                             }
                         }
                     }
-                    TokenClass::Identifire(_id) => {
-                        let l = self.wire_module_list();
-                        Some(create_node!(ASTClass::Submodule(id_node, l)))
+                    TokenClass::Identifire(_) => {
+                        let l = self.wire_module_list()?;
+                        Ok(Some(create_node!(ASTClass::Submodule(id_node, l))))
+                    }
+                    // <struct name> wire <id>, ... ;
+                    TokenClass::Symbol(Symbol::Wire) => {
+                        self.lexer.next(true);
+                        // like a plain wire, a struct wire takes no initializer
+                        let ids = self.identifier_list()?;
+                        Ok(Some(create_node!(ASTClass::StructWire(id_node, ids))))
+                    }
+                    // <struct name> reg <id> [= <expression>], ... ;
+                    TokenClass::Symbol(Symbol::Reg) => {
+                        self.lexer.next(true);
+                        let regs = self.struct_member_list()?;
+                        Ok(Some(create_node!(ASTClass::StructReg(id_node, regs))))
                     }
                     _ => {
-                        unexpected_token!(next_t);
+                        unexpected_token!(
+                            next_t,
+                            TokenKind::Symbol(Symbol::Equal),
+                            TokenKind::Symbol(Symbol::RegAssign),
+                            TokenKind::Symbol(Symbol::LeftParen),
+                            TokenKind::Symbol(Symbol::Dot),
+                            TokenKind::Symbol(Symbol::LeftSquareBracket),
+                            TokenKind::Symbol(Symbol::Colon),
+                            TokenKind::UnaryOperator,
+                            TokenKind::Identifire
+                        );
                     }
                 }
             }
-            TokenClass::Symbol(Symbol::ProcName) => {
-                let id_node = self.generate_id_node();
-                self.check_left_paren();
-                let args_vec = self.generate_args_vec();
-                self.check_semicolon();
-                Some(create_node!(ASTClass::ProcName(id_node, args_vec,)))
+            // {a, b[3:0]} = c;
+            TokenClass::Symbol(Symbol::OpeningBrace) => {
+                let target = self.concatenation(t.span)?;
+                Ok(Some(self.assignment_ast(target)?))
             }
-            TokenClass::Symbol(Symbol::StateName) => {
-                let mut ids = vec![];
+            TokenClass::Symbol(Symbol::ProcName) => {
+                let mut procs = vec![];
                 loop {
-                    let id_node = self.generate_id_node();
-                    ids.push(id_node);
+                    let id_node = self.generate_id_node()?;
+                    self.check_left_paren()?;
+                    let args_vec = self.generate_args_vec()?;
+                    procs.push((id_node, args_vec));
 
                     let n_token = self.lexer.next(true);
                     match n_token.class {
@@ -374,130 +636,185 @@ impl<'a> Parser<'a> {
                             continue;
                         }
                         _ => {
-                            unexpected_token!(n_token);
+                            unexpected_token!(
+                                n_token,
+                                TokenKind::Symbol(Symbol::Comma),
+                                TokenKind::Symbol(Symbol::Semicolon)
+                            );
                         }
                     }
                 }
-                Some(create_node!(ASTClass::StateName(ids)))
+                Ok(Some(create_node!(ASTClass::ProcName(procs))))
+            }
+            TokenClass::Symbol(Symbol::Proc) => {
+                let id_node = self.generate_id_node()?;
+                let block = self.module_block_ast()?;
+                Ok(Some(create_node!(ASTClass::Proc(id_node, block))))
+            }
+            TokenClass::Symbol(Symbol::Finish) => {
+                self.check_semicolon()?;
+                Ok(Some(create_node!(ASTClass::Finish)))
+            }
+            TokenClass::Symbol(Symbol::Invoke) => {
+                let call = self.expression_ast()?;
+                self.check_semicolon()?;
+                Ok(Some(create_node!(ASTClass::Invoke(call))))
+            }
+            TokenClass::Symbol(Symbol::StateName) => {
+                let ids = self.identifier_list()?;
+                Ok(Some(create_node!(ASTClass::StateName(ids))))
+            }
+            TokenClass::Symbol(Symbol::LabelName) => {
+                let ids = self.identifier_list()?;
+                Ok(Some(create_node!(ASTClass::LabelName(ids))))
             }
             TokenClass::Symbol(Symbol::State) => {
-                let id_node = self.generate_id_node();
-                let block = self.module_block_ast();
-                Some(create_node!(ASTClass::State(id_node, block)))
+                let id_node = self.generate_id_node()?;
+                let block = self.module_block_ast()?;
+                Ok(Some(create_node!(ASTClass::State(id_node, block))))
             }
             TokenClass::Symbol(Symbol::FuncSelf) => {
-                let id_node = self.generate_id_node();
+                let id_node = self.generate_id_node()?;
                 let args_vec = if TokenClass::Symbol(Symbol::LeftParen)
                     == self.lexer.peek(true).class
                 {
                     self.lexer.next(true);
-                    self.generate_args_vec()
+                    self.generate_args_vec()?
                 } else {
                     vec![]
                 };
-                let return_port = self.generate_func_return();
-                Some(create_node!(ASTClass::FuncSelf(
+                let return_port = self.generate_func_return()?;
+                Ok(Some(create_node!(ASTClass::FuncSelf(
                     id_node,
                     args_vec,
                     return_port,
-                )))
+                ))))
             }
             TokenClass::Symbol(Symbol::Func) => {
-                let id_node = self.generate_id_node();
+                let id_node = self.generate_id_node()?;
                 let mut func_name_node: Option<Box<ASTNode>> = None;
                 if TokenClass::Symbol(Symbol::Dot) == self.lexer.peek(true).class {
                     self.lexer.next(true);
-                    func_name_node = Some(self.generate_id_node());
+                    func_name_node = Some(self.generate_id_node()?);
                 }
 
-                let block = self.module_block_ast();
-                Some(create_node!(ASTClass::Func(id_node, func_name_node, block)))
+                let block = self.module_block_ast()?;
+                Ok(Some(create_node!(ASTClass::Func(
+                    id_node,
+                    func_name_node,
+                    block
+                ))))
             }
             TokenClass::Symbol(Symbol::Return) => {
-                let expr = self.expression_ast();
-                self.check_semicolon();
-                Some(create_node!(ASTClass::Return(expr)))
+                let expr = self.expression_ast()?;
+                self.check_semicolon()?;
+                Ok(Some(create_node!(ASTClass::Return(expr))))
             }
             TokenClass::Symbol(Symbol::If) => {
-                self.check_left_paren();
-                let expr_ast = self.expression_ast();
-                self.check_right_paren();
+                self.check_left_paren()?;
+                let expr_ast = self.expression_ast()?;
+                self.check_right_paren()?;
                 let n_t = self.lexer.peek(true);
                 let if_block = if let TokenClass::Symbol(Symbol::OpeningBrace) = n_t.class
                 {
-                    self.module_block_ast()
+                    self.module_block_ast()?
                 } else {
-                    let bb = if let Some(b) = self.module_block_part_ast() {
+                    if let Some(b) = self.module_block_part_ast()? {
                         b
                     } else {
-                        create_node!(ASTClass::Block(vec![]))
-                    };
-                    bb
+                        create_node!(ASTClass::Block(vec![]), self.span_from(n_t.span))
+                    }
                 };
 
                 let else_block =
                     if TokenClass::Symbol(Symbol::Else) == self.lexer.peek(true).class {
                         self.lexer.next(true);
-                        let block =
-                            if let TokenClass::Symbol(Symbol::OpeningBrace) = n_t.class {
-                                Some(self.module_block_ast())
-                            } else {
-                                self.module_block_part_ast()
-                            };
-                        block
+                        // each branch is a block or a single statement on its own
+                        let e_t = self.lexer.peek(true);
+                        if let TokenClass::Symbol(Symbol::OpeningBrace) = e_t.class {
+                            Some(self.module_block_ast()?)
+                        } else {
+                            self.module_block_part_ast()?
+                        }
                     } else {
                         None
                     };
 
-                Some(create_node!(ASTClass::If(expr_ast, if_block, else_block)))
+                Ok(Some(create_node!(ASTClass::If(
+                    expr_ast, if_block, else_block
+                ))))
             }
             TokenClass::Symbol(Symbol::Any) => {
-                self.check_opening_brace();
-
-                let mut any_components = vec![];
-
-                loop {
-                    let next_t = self.lexer.peek(true);
-
-                    match next_t.class {
-                        TokenClass::Symbol(Symbol::ClosingBrace) => {
-                            self.lexer.next(true);
-                            break;
-                        }
-                        TokenClass::Symbol(Symbol::Else) => {
-                            self.lexer.next(true);
-                            self.check_colon();
-                            let block = self.module_block_ast();
-                            any_components.push((create_node!(ASTClass::Else, next_t.position), block));
-                        }
-                        _ => {
-                            let ast = self.expression_ast();
-                            self.check_colon();
-                            let block = self.module_block_ast();
-                            any_components.push((ast, block));
-                        }
-                    }
-                }
-                Some(create_node!(ASTClass::Any(any_components)), t.position)
+                let any_components = self.condition_arms(context_of(&t.class))?;
+                Ok(Some(create_node!(ASTClass::Any(any_components))))
+            }
+            TokenClass::Symbol(Symbol::Alt) => {
+                let alt_components = self.condition_arms(context_of(&t.class))?;
+                Ok(Some(create_node!(ASTClass::Alt(alt_components))))
+            }
+            TokenClass::Symbol(Symbol::Seq) => {
+                let block = self.module_block_ast()?;
+                Ok(Some(create_node!(ASTClass::Seq(block))))
+            }
+            TokenClass::Symbol(Symbol::For) => {
+                self.check_left_paren()?;
+                let init = self.loop_statement()?;
+                self.check_semicolon()?;
+                let cond = self.expression_ast()?;
+                self.check_semicolon()?;
+                let step = self.loop_statement()?;
+                self.check_right_paren()?;
+                let block = self.loop_body()?;
+                Ok(Some(create_node!(ASTClass::For(init, cond, step, block))))
             }
-            TokenClass::UnaryOperator(op) => {
-                let id = self.generate_id_node();
-                self.check_semicolon();
-                Some(create_node!(ASTClass::UnaryOperation(
-                    create_node!(ASTClass::UnaryOperator(op)),
+            TokenClass::Symbol(Symbol::Generate) => {
+                self.check_left_paren()?;
+                let init = self.loop_statement()?;
+                self.check_semicolon()?;
+                let cond = self.expression_ast()?;
+                self.check_semicolon()?;
+                let step = self.loop_statement()?;
+                self.check_right_paren()?;
+                let block = self.module_block_ast()?;
+                Ok(Some(create_node!(ASTClass::Generate(
+                    init, cond, step, block
+                ))))
+            }
+            TokenClass::Symbol(Symbol::Integer) => {
+                let ids = self.identifier_list()?;
+                Ok(Some(create_node!(ASTClass::Integer(ids))))
+            }
+            TokenClass::Symbol(Symbol::Variable) => {
+                let l = self.wire_module_list()?;
+                Ok(Some(create_node!(ASTClass::Variable(l))))
+            }
+            TokenClass::Symbol(Symbol::While) => {
+                self.check_left_paren()?;
+                let cond = self.expression_ast()?;
+                self.check_right_paren()?;
+                let block = self.loop_body()?;
+                Ok(Some(create_node!(ASTClass::While(cond, block))))
+            }
+            TokenClass::UnaryOperator(op) if op.is_postfix() => {
+                let id = self.generate_id_node()?;
+                self.check_semicolon()?;
+                Ok(Some(create_node!(ASTClass::UnaryOperation(
+                    create_node!(ASTClass::UnaryOperator(op), t.span),
                     id
-                )))
+                ))))
             }
-            TokenClass::Symbol(Symbol::Semicolon) => None,
+            TokenClass::Symbol(Symbol::Semicolon) => Ok(None),
+            TokenClass::Symbol(Symbol::Sharp) => Ok(Some(self.macro_ast()?)),
             TokenClass::Symbol(Symbol::Goto) => {
-                let id = self.generate_id_node();
-                Some(create_node!(ASTClass::Goto(id)))
+                let id = self.generate_id_node()?;
+                self.check_semicolon()?;
+                Ok(Some(create_node!(ASTClass::Goto(id))))
             }
             TokenClass::CPPStyleComment(comment) => {
-                Some(create_node!(ASTClass::CPPStyleComment(comment)))
+                Ok(Some(create_node!(ASTClass::CPPStyleComment(comment))))
             }
             TokenClass::CStyleComment(list) => {
-                Some(create_node!(ASTClass::CStyleComment(list)))
+                Ok(Some(create_node!(ASTClass::CStyleComment(list))))
             }
             _ => {
                 unexpected_token!(t);
@@ -505,410 +822,1027 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /*
+     * the body of `any` and `alt`
+     *  {
+     *      <expression>: <block or a statement>
+     *      else: <block or a statement>
+     *  }
+     * a directive may come between the arms.
+     * an arm with a broken condition is skipped as a whole when recovering.
+     */
+    #[allow(clippy::type_complexity)]
+    fn condition_arms(
+        &mut self,
+        context: Option<String>,
+    ) -> Result<Vec<(Box<ASTNode>, Option<Box<ASTNode>>)>, ParseError> {
+        self.check_opening_brace()?;
+
+        let mut components = vec![];
+
+        loop {
+            let next_t = self.lexer.peek(true);
+            match next_t.class {
+                TokenClass::Symbol(Symbol::ClosingBrace) => {
+                    self.lexer.next(true);
+                    break;
+                }
+                TokenClass::EndOfProgram => {
+                    unexpected_token!(next_t, TokenKind::Symbol(Symbol::ClosingBrace));
+                }
+                TokenClass::Symbol(Symbol::Sharp) => {
+                    self.lexer.next(true);
+                    let directive = self.macro_ast()?;
+                    components.push((self.spanned(directive, next_t.span), None));
+                    continue;
+                }
+                _ => {}
+            }
+
+            let condition = match self.arm_condition() {
+                Ok(condition) => condition,
+                Err(e) if self.recovery => {
+                    let error = self.recover(e.within(context.clone()));
+                    components.push((error, Some(create_node!(ASTClass::Block(vec![])))));
+                    if self.closed_by_error {
+                        self.closed_by_error = false;
+                        break;
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let n_t = self.lexer.peek(true);
+            let block = if let TokenClass::Symbol(Symbol::OpeningBrace) = n_t.class {
+                self.module_block_ast()?
+            } else if let Some(statement) = self.module_block_part_ast()? {
+                statement
+            } else {
+                create_node!(ASTClass::Block(vec![]), self.span_from(n_t.span))
+            };
+            components.push((condition, Some(block)));
+
+            if self.closed_by_error {
+                self.closed_by_error = false;
+                break;
+            }
+        }
+        Ok(components)
+    }
+
+    /*
+     * the initialization and the step of `for`, which have no `;`
+     * e.g. i = 0, i := i + 1, i++
+     */
+    fn loop_statement(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let start = self.lexer.peek(true).span;
+        let left = self.expression_ast()?;
+        let t = self.lexer.peek(true);
+        let node = match t.class {
+            TokenClass::Symbol(Symbol::Equal) => {
+                self.lexer.next(true);
+                let expr = self.expression_ast()?;
+                create_node!(ASTClass::Assign(left, expr))
+            }
+            TokenClass::Symbol(Symbol::RegAssign) => {
+                self.lexer.next(true);
+                let expr = self.expression_ast()?;
+                create_node!(ASTClass::RegAssign(left, expr))
+            }
+            _ => left,
+        };
+        Ok(self.spanned(node, start))
+    }
+
+    /*
+     * the body of `for` and `while`, a block or a statement
+     */
+    fn loop_body(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let n_t = self.lexer.peek(true);
+        if let TokenClass::Symbol(Symbol::OpeningBrace) = n_t.class {
+            return self.module_block_ast();
+        }
+        match self.module_block_part_ast()? {
+            Some(statement) => Ok(statement),
+            None => Ok(create_node!(
+                ASTClass::Block(vec![]),
+                self.span_from(n_t.span)
+            )),
+        }
+    }
+
+    // <id>, <id>, ... ;
+    #[allow(clippy::vec_box)]
+    fn identifier_list(&mut self) -> Result<Vec<Box<ASTNode>>, ParseError> {
+        let mut ids = vec![];
+        loop {
+            ids.push(self.generate_id_node()?);
+
+            let n_token = self.lexer.next(true);
+            match n_token.class {
+                TokenClass::Symbol(Symbol::Semicolon) => {
+                    return Ok(ids);
+                }
+                TokenClass::Symbol(Symbol::Comma) => {
+                    continue;
+                }
+                _ => {
+                    unexpected_token!(
+                        n_token,
+                        TokenKind::Symbol(Symbol::Comma),
+                        TokenKind::Symbol(Symbol::Semicolon)
+                    );
+                }
+            }
+        }
+    }
+
+    /*
+     * the declared names of a struct-typed reg
+     * <id> [= <expression>] { , <id> [= <expression>] }* ;
+     */
+    // <expression>: or else:
+    fn arm_condition(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let t = self.lexer.peek(true);
+        let condition = if let TokenClass::Symbol(Symbol::Else) = t.class {
+            self.lexer.next(true);
+            create_node!(ASTClass::Else, t.span)
+        } else {
+            self.expression_ast()?
+        };
+        self.check_colon()?;
+        Ok(condition)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn struct_member_list(
+        &mut self,
+    ) -> Result<Vec<(Box<ASTNode>, Option<Box<ASTNode>>)>, ParseError> {
+        let mut list = vec![];
+        loop {
+            let id_node = self.generate_id_node()?;
+            let init = if TokenClass::Symbol(Symbol::Equal) == self.lexer.peek(true).class
+            {
+                self.lexer.next(true);
+                Some(self.expression_ast()?)
+            } else {
+                None
+            };
+            list.push((id_node, init));
+
+            let n_token = self.lexer.next(true);
+            match n_token.class {
+                TokenClass::Symbol(Symbol::Semicolon) => {
+                    return Ok(list);
+                }
+                TokenClass::Symbol(Symbol::Comma) => {
+                    continue;
+                }
+                _ => {
+                    unexpected_token!(
+                        n_token,
+                        TokenKind::Symbol(Symbol::Equal),
+                        TokenKind::Symbol(Symbol::Comma),
+                        TokenKind::Symbol(Symbol::Semicolon)
+                    );
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
     fn mem_definition(
         &mut self,
-    ) -> (
-        Box<ASTNode>,
-        Box<ASTNode>,
-        Option<Box<ASTNode>>,
-        Option<Vec<Box<ASTNode>>>,
-    ) {
-        let id_node = self.generate_id_node();
-        self.check_left_square_bracket();
-        let width_ast = self.expression_ast();
-        self.check_right_square_bracket();
+    ) -> Result<
+        (
+            Box<ASTNode>,
+            Box<ASTNode>,
+            Option<Box<ASTNode>>,
+            Option<Vec<Box<ASTNode>>>,
+        ),
+        ParseError,
+    > {
+        let id_node = self.generate_id_node()?;
+        self.check_left_square_bracket()?;
+        let width_ast = self.expression_ast()?;
+        self.check_right_square_bracket()?;
 
         let mut width_ast2: Option<Box<ASTNode>> = None;
 
         let t = self.lexer.peek(true);
         if TokenClass::Symbol(Symbol::Semicolon) == t.class {
-            return (id_node, width_ast, None, None);
+            return Ok((id_node, width_ast, None, None));
         }
 
         if TokenClass::Symbol(Symbol::LeftSquareBracket) == t.class {
             self.lexer.next(true);
-            let w_ast = self.expression_ast();
+            let w_ast = self.expression_ast()?;
             width_ast2 = Some(w_ast);
-            self.check_right_square_bracket();
+            self.check_right_square_bracket()?;
         }
 
         let next = self.lexer.peek(true);
         if TokenClass::Symbol(Symbol::Equal) == next.class {
             self.lexer.next(true);
-            let initial_values = self.mem_initialize_block();
-            return (id_node, width_ast, width_ast2, Some(initial_values));
+            let initial_values = self.mem_initialize_block()?;
+            Ok((id_node, width_ast, width_ast2, Some(initial_values)))
         } else {
-            return (id_node, width_ast, width_ast2, None);
+            Ok((id_node, width_ast, width_ast2, None))
         }
     }
 
-    fn mem_initialize_block(&mut self) -> Vec<Box<ASTNode>> {
-        self.check_opening_brace();
+    #[allow(clippy::vec_box)]
+    fn mem_initialize_block(&mut self) -> Result<Vec<Box<ASTNode>>, ParseError> {
+        self.check_opening_brace()?;
         let mut contents_in_block = vec![];
         loop {
-            let next = self.lexer.next(true);
+            let next = self.lexer.peek(true);
             match next.class {
                 TokenClass::Symbol(Symbol::ClosingBrace) => {
+                    self.lexer.next(true);
                     break;
                 }
-                TokenClass::Number(num) => {
-                    contents_in_block.push(create_node!(ASTClass::Number(num)));
-                }
                 TokenClass::Symbol(Symbol::Comma) => {
+                    self.lexer.next(true);
                     continue;
                 }
+                // a number, a macro or an expression
                 _ => {
-                    unexpected_token!(next);
+                    contents_in_block.push(self.expression_ast()?);
                 }
             }
         }
-        contents_in_block
+        Ok(contents_in_block)
     }
 
+    #[allow(clippy::type_complexity)]
     fn reg_definition(
         &mut self,
-    ) -> (Box<ASTNode>, Option<Box<ASTNode>>, Option<Box<ASTNode>>) {
-        let id_node = self.generate_id_node();
+    ) -> Result<(Box<ASTNode>, Option<Box<ASTNode>>, Option<Box<ASTNode>>), ParseError>
+    {
+        let id_node = self.generate_id_node()?;
         let t = self.lexer.peek(true);
         let width_ast = match t.class {
             TokenClass::Symbol(Symbol::Semicolon) => {
-                return (id_node, None, None);
+                return Ok((id_node, None, None));
             }
             TokenClass::Symbol(Symbol::Comma) => {
-                return (id_node, None, None);
+                return Ok((id_node, None, None));
             }
             TokenClass::Symbol(Symbol::Equal) => None,
             TokenClass::Symbol(Symbol::LeftSquareBracket) => {
                 self.lexer.next(true);
-                let width_ast = self.expression_ast();
-                self.check_right_square_bracket();
+                let width_ast = self.expression_ast()?;
+                self.check_right_square_bracket()?;
 
                 let next_t = self.lexer.peek(true);
                 if TokenClass::Symbol(Symbol::Semicolon) == next_t.class
                     || TokenClass::Symbol(Symbol::Comma) == next_t.class
                 {
-                    return (id_node, Some(width_ast), None);
+                    return Ok((id_node, Some(width_ast), None));
                 }
 
                 if TokenClass::Symbol(Symbol::Equal) != next_t.class {
-                    unexpected_token!(next_t);
+                    unexpected_token!(
+                        next_t,
+                        TokenKind::Symbol(Symbol::Equal),
+                        TokenKind::Symbol(Symbol::Comma),
+                        TokenKind::Symbol(Symbol::Semicolon)
+                    );
                 }
                 Some(width_ast)
             }
             _ => {
-                unexpected_token!(t);
+                unexpected_token!(
+                    t,
+                    TokenKind::Symbol(Symbol::LeftSquareBracket),
+                    TokenKind::Symbol(Symbol::Equal),
+                    TokenKind::Symbol(Symbol::Comma),
+                    TokenKind::Symbol(Symbol::Semicolon)
+                );
             }
         };
 
         let next_t = self.lexer.next(true);
         if TokenClass::Symbol(Symbol::Equal) == next_t.class {
-            let expr_ast = self.expression_ast();
-            return (id_node, width_ast, Some(expr_ast));
+            let expr_ast = self.expression_ast()?;
+            return Ok((id_node, width_ast, Some(expr_ast)));
         }
 
-        unexpected_token!(next_t);
+        unexpected_token!(next_t, TokenKind::Symbol(Symbol::Equal));
     }
 
-    fn get_id_and_width(&mut self) -> (Box<ASTNode>, Option<Box<ASTNode>>) {
-        let id_node = self.generate_id_node();
-        match self.lexer.next(true).class {
-            TokenClass::Symbol(Symbol::Semicolon) => (id_node, None),
+    fn get_id_and_width(
+        &mut self,
+    ) -> Result<(Box<ASTNode>, Option<Box<ASTNode>>), ParseError> {
+        let id_node = self.generate_id_node()?;
+        let t = self.lexer.next(true);
+        match t.class {
+            TokenClass::Symbol(Symbol::Semicolon) => Ok((id_node, None)),
             TokenClass::Symbol(Symbol::LeftSquareBracket) => {
-                let expr = self.expression_ast();
-                self.check_right_square_bracket();
-                self.check_semicolon();
-                (id_node, Some(expr))
+                let expr = self.expression_ast()?;
+                self.check_right_square_bracket()?;
+                self.check_semicolon()?;
+                Ok((id_node, Some(expr)))
             }
             _ => {
-                not_implemented!();
+                unexpected_token!(
+                    t,
+                    TokenKind::Symbol(Symbol::LeftSquareBracket),
+                    TokenKind::Symbol(Symbol::Semicolon)
+                );
             }
         }
     }
 
-    fn declare_block_part_ast(&mut self) -> Box<ASTNode> {
+    /*
+     * <id> [= <default>];
+     */
+    fn param_definition(
+        &mut self,
+    ) -> Result<(Box<ASTNode>, Option<Box<ASTNode>>), ParseError> {
+        let id_node = self.generate_id_node()?;
+        if TokenClass::Symbol(Symbol::Equal) != self.lexer.peek(true).class {
+            self.check_semicolon()?;
+            return Ok((id_node, None));
+        }
+        self.lexer.next(true);
+        let value = self.lexer.peek(true);
+        let default = if let TokenClass::String(s) = value.class {
+            self.lexer.next(true);
+            create_node!(ASTClass::String(s), value.span)
+        } else {
+            self.expression_ast()?
+        };
+        self.check_semicolon()?;
+        Ok((id_node, Some(default)))
+    }
+
+    fn declare_block_part_ast(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let first = self.lexer.peek(true);
+        let context = context_of(&first.class);
+        let node = match self.declare_statement_ast() {
+            Ok(node) => node,
+            Err(e) if self.recovery => self.recover(e.within(context)),
+            Err(e) => return Err(e.within(context)),
+        };
+        Ok(self.spanned(node, first.span))
+    }
+
+    fn declare_statement_ast(&mut self) -> Result<Box<ASTNode>, ParseError> {
         let t = self.lexer.next(true);
-        return match t.class {
+        match t.class {
+            TokenClass::Identifire(ref id) if self.is_macro_statement(id) => {
+                Ok(self.macro_statement(&t))
+            }
             TokenClass::Symbol(Symbol::Input) => {
-                let (id_node, width) = self.get_id_and_width();
-                create_node!(ASTClass::Input(id_node, width))
+                let (id_node, width) = self.get_id_and_width()?;
+                Ok(create_node!(ASTClass::Input(id_node, width)))
             }
             TokenClass::Symbol(Symbol::Output) => {
-                let (id_node, width) = self.get_id_and_width();
-                create_node!(ASTClass::Output(id_node, width))
+                let (id_node, width) = self.get_id_and_width()?;
+                Ok(create_node!(ASTClass::Output(id_node, width)))
             }
             TokenClass::Symbol(Symbol::InOut) => {
-                let (id_node, width) = self.get_id_and_width();
-                create_node!(ASTClass::InOut(id_node, width))
+                let (id_node, width) = self.get_id_and_width()?;
+                Ok(create_node!(ASTClass::InOut(id_node, width)))
             }
             TokenClass::Symbol(Symbol::FuncIn) => {
-                let id_node = self.generate_id_node();
+                let id_node = self.generate_id_node()?;
                 let args_vec = if TokenClass::Symbol(Symbol::Semicolon)
                     == self.lexer.peek(true).class
                 {
                     vec![]
                 } else {
-                    self.check_left_paren();
-                    self.generate_args_vec()
+                    self.check_left_paren()?;
+                    self.generate_args_vec()?
                 };
 
-                let return_port = self.generate_func_return();
-                create_node!(ASTClass::FuncIn(id_node, args_vec, return_port,))
+                let return_port = self.generate_func_return()?;
+                Ok(create_node!(ASTClass::FuncIn(
+                    id_node,
+                    args_vec,
+                    return_port
+                )))
             }
             TokenClass::Symbol(Symbol::FuncOut) => {
-                let id_node = self.generate_id_node();
+                let id_node = self.generate_id_node()?;
                 let args_vec = if TokenClass::Symbol(Symbol::Semicolon)
                     == self.lexer.peek(true).class
                 {
                     vec![]
                 } else {
-                    self.check_left_paren();
-                    self.generate_args_vec()
+                    self.check_left_paren()?;
+                    self.generate_args_vec()?
                 };
 
-                let return_port = self.generate_func_return();
-                create_node!(ASTClass::FuncOut(id_node, args_vec, return_port,))
+                let return_port = self.generate_func_return()?;
+                Ok(create_node!(ASTClass::FuncOut(
+                    id_node,
+                    args_vec,
+                    return_port
+                )))
+            }
+            TokenClass::Symbol(Symbol::ParamInt) => {
+                let (id_node, default) = self.param_definition()?;
+                Ok(create_node!(ASTClass::Param(
+                    Symbol::ParamInt,
+                    id_node,
+                    default
+                )))
+            }
+            TokenClass::Symbol(Symbol::ParamStr) => {
+                let (id_node, default) = self.param_definition()?;
+                Ok(create_node!(ASTClass::Param(
+                    Symbol::ParamStr,
+                    id_node,
+                    default
+                )))
+            }
+            TokenClass::Symbol(Symbol::Sharp) => self.macro_ast(),
+            TokenClass::CPPStyleComment(comment) => {
+                Ok(create_node!(ASTClass::CPPStyleComment(comment)))
+            }
+            TokenClass::CStyleComment(list) => {
+                Ok(create_node!(ASTClass::CStyleComment(list)))
             }
             _ => {
-                unexpected_token!(t);
+                unexpected_token!(
+                    t,
+                    TokenKind::Symbol(Symbol::Input),
+                    TokenKind::Symbol(Symbol::Output),
+                    TokenKind::Symbol(Symbol::InOut),
+                    TokenKind::Symbol(Symbol::FuncIn),
+                    TokenKind::Symbol(Symbol::FuncOut),
+                    TokenKind::Symbol(Symbol::ParamInt),
+                    TokenKind::Symbol(Symbol::ParamStr),
+                    TokenKind::Symbol(Symbol::Sharp)
+                );
             }
-        };
+        }
     }
 
-    fn to_node(&self, t: Token) -> Box<ASTNode> {
-        return match t.class {
-            TokenClass::Number(num) => create_node!(ASTClass::Number(num)),
-            TokenClass::Identifire(id) => create_node!(ASTClass::Identifire(id)),
+    fn to_node(&self, t: Token) -> Result<Box<ASTNode>, ParseError> {
+        match t.class {
+            TokenClass::Number(num) => {
+                Ok(create_node!(ASTClass::Number(num.spelling), t.span))
+            }
+            TokenClass::Identifire(id) => {
+                Ok(create_node!(ASTClass::Identifire(id), t.span))
+            }
             _ => {
-                unexpected_token!(t);
+                unexpected_token!(
+                    t,
+                    TokenKind::Identifire,
+                    TokenKind::Number,
+                    TokenKind::UnaryOperator,
+                    TokenKind::Symbol(Symbol::LeftParen),
+                    TokenKind::Symbol(Symbol::OpeningBrace)
+                );
             }
-        };
+        }
+    }
+
+    /*
+     * the rest of `<target> = <expression>;` or `<target> := <expression>;`
+     */
+    fn assignment_ast(
+        &mut self,
+        target: Box<ASTNode>,
+    ) -> Result<Box<ASTNode>, ParseError> {
+        let assigner = self.lexer.next(true);
+        match assigner.class {
+            TokenClass::Symbol(Symbol::Equal) | TokenClass::Symbol(Symbol::RegAssign) => {
+            }
+            _ => {
+                unexpected_token!(
+                    assigner,
+                    TokenKind::Symbol(Symbol::Equal),
+                    TokenKind::Symbol(Symbol::RegAssign)
+                );
+            }
+        }
+        let right = self.expression_ast()?;
+        self.check_semicolon()?;
+        if assigner.class == TokenClass::Symbol(Symbol::Equal) {
+            Ok(create_node!(ASTClass::Assign(target, right)))
+        } else {
+            Ok(create_node!(ASTClass::RegAssign(target, right)))
+        }
     }
 
-    fn bit_slice(&mut self) -> Box<ASTNode> {
-        let msb = self.expression_ast();
+    /*
+     * after `{`
+     *  {<expression>, ...}
+     *  {<count>{<expression>, ...}}
+     */
+    fn concatenation(&mut self, start: Span) -> Result<Box<ASTNode>, ParseError> {
+        let first = self.expression_ast()?;
+        if TokenClass::Symbol(Symbol::OpeningBrace) == self.lexer.peek(true).class {
+            self.lexer.next(true);
+            let head = self.expression_ast()?;
+            let items = self.concatenation_items(head)?;
+            self.check_symbol(Symbol::ClosingBrace)?;
+            return Ok(create_node!(
+                ASTClass::Repeat(first, items),
+                self.span_from(start)
+            ));
+        }
+        let items = self.concatenation_items(first)?;
+        Ok(create_node!(ASTClass::Concat(items), self.span_from(start)))
+    }
+
+    // a comma separated list of expressions closed by `}`
+    #[allow(clippy::vec_box)]
+    fn concatenation_items(
+        &mut self,
+        head: Box<ASTNode>,
+    ) -> Result<Vec<Box<ASTNode>>, ParseError> {
+        let mut items = vec![head];
+        loop {
+            let t = self.lexer.next(true);
+            match t.class {
+                TokenClass::Symbol(Symbol::Comma) => items.push(self.expression_ast()?),
+                TokenClass::Symbol(Symbol::ClosingBrace) => return Ok(items),
+                _ => {
+                    unexpected_token!(
+                        t,
+                        TokenKind::Symbol(Symbol::Comma),
+                        TokenKind::Symbol(Symbol::ClosingBrace)
+                    );
+                }
+            }
+        }
+    }
+
+    fn bit_slice(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let msb = self.expression_ast()?;
         if let TokenClass::Symbol(Symbol::Colon) = self.lexer.peek(true).class {
             self.lexer.next(true);
-            let lsb = self.expression_ast();
-            create_node!(ASTClass::BitSlice(msb, Some(lsb)))
+            let lsb = self.expression_ast()?;
+            let span = msb.span.to(&lsb.span);
+            Ok(create_node!(ASTClass::BitSlice(msb, Some(lsb)), span))
         } else {
-            msb
+            Ok(msb)
         }
     }
 
-    fn expression_ast(&mut self) -> (Box<ASTNode>) {
-        //xxx have to rafactor
-        let t = self.lexer.next(true);
-        let left = match t.class {
-            TokenClass::UnaryOperator(op) => {
-                //                 let id = self.generate_id_node();
-                let id = self.expression_ast();
-                create_node!(ASTClass::UnaryOperation(
-                    create_node!(ASTClass::UnaryOperator(op)),
-                    id
-                ))
-            }
-            TokenClass::Symbol(Symbol::LeftParen) => {
-                let inner_expr = self.expression_ast();
-                self.check_right_paren();
-                inner_expr
+    /*
+     * <cond> ? <expression> : <expression>, binds looser than any operator.
+     * it is right associative, a ? b : c ? d : e is a ? b : (c ? d : e)
+     */
+    fn expression_ast(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let start = self.lexer.peek(true).span;
+        let cond = self.binary_expression(1)?;
+        if TokenClass::Symbol(Symbol::Question) != self.lexer.peek(true).class {
+            return Ok(cond);
+        }
+        self.lexer.next(true);
+        let then_expr = self.expression_ast()?;
+        self.check_colon()?;
+        let else_expr = self.expression_ast()?;
+        Ok(create_node!(
+            ASTClass::Ternary(cond, then_expr, else_expr),
+            self.span_from(start)
+        ))
+    }
+
+    /*
+     * precedence climbing, every operator binds at least as tight as `min_prec`
+     */
+    fn binary_expression(&mut self, min_prec: usize) -> Result<Box<ASTNode>, ParseError> {
+        let start = self.lexer.peek(true).span;
+        let mut left = self.unary_expression()?;
+        loop {
+            let t = self.lexer.peek(true);
+            let op = match t.class {
+                TokenClass::Operator(op) => op,
+                _ => break,
+            };
+            let prec = op.precedence();
+            if prec < min_prec {
+                break;
             }
-            _ => self.to_node(t),
-        };
+            self.lexer.next(true);
+            // left associative, so the right side only takes tighter operators
+            let right = self.binary_expression(prec + 1)?;
+            left = create_node!(
+                ASTClass::Expression(
+                    left,
+                    create_node!(ASTClass::Operator(op), t.span),
+                    right
+                ),
+                self.span_from(start)
+            );
+        }
+        Ok(left)
+    }
 
-        let n_token = self.lexer.peek(true);
+    /*
+     * `&`, `|`, `^` and `-` are binary operators after an operand,
+     * and reduction operators or the negation where an operand is expected
+     */
+    fn unary_expression(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let t = self.lexer.peek(true);
+        let op = match t.class {
+            TokenClass::UnaryOperator(ref op) => Some(op.clone()),
+            TokenClass::Operator(ref op) => op.as_unary(),
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.lexer.next(true);
+            let operand = self.unary_expression()?;
+            return Ok(create_node!(
+                ASTClass::UnaryOperation(
+                    create_node!(ASTClass::UnaryOperator(op), t.span),
+                    operand
+                ),
+                self.span_from(t.span)
+            ));
+        }
+        self.postfix_expression()
+    }
 
-        let left_node = match n_token.class {
+    fn postfix_expression(&mut self) -> Result<Box<ASTNode>, ParseError> {
+        let t = self.lexer.next(true);
+        let start = t.span;
+        let mut left = match t.class {
             TokenClass::Symbol(Symbol::LeftParen) => {
-                self.lexer.next(true);
-                let args = self.generate_args_vec();
-                create_node!(ASTClass::FuncCall(left, args, None))
+                let inner_expr = self.expression_ast()?;
+                self.check_right_paren()?;
+                create_node!(ASTClass::Paren(inner_expr), self.span_from(start))
             }
-            TokenClass::Symbol(Symbol::LeftSquareBracket) => {
-                self.lexer.next(true);
-                let width_expr = self.bit_slice();
-                self.check_right_square_bracket();
-                create_node!(ASTClass::BitslicedExpr(left, width_expr))
-            }
-            TokenClass::UnaryOperator(op) => {
-                self.lexer.next(true);
-                create_node!(ASTClass::UnaryOperation(
-                    left,
-                    create_node!(ASTClass::UnaryOperator(op))
-                ))
-            }
-            TokenClass::Symbol(Symbol::Dot) => {
-                self.lexer.next(true);
-                let port_id = self.generate_id_node();
-                // e.g test.ok()
-                if TokenClass::Symbol(Symbol::LeftParen) == self.lexer.peek(true).class {
+            TokenClass::Symbol(Symbol::OpeningBrace) => self.concatenation(start)?,
+            _ => self.to_node(t)?,
+        };
+
+        // postfix operations, they can be chained. e.g. r.field[3:0], sub.port.f()
+        loop {
+            let n_token = self.lexer.peek(true);
+            left = match n_token.class {
+                TokenClass::Symbol(Symbol::LeftParen) => {
                     self.lexer.next(true);
-                    let args = self.generate_args_vec();
-                    create_node!(ASTClass::FuncCall(left, args, Some(port_id)))
+                    let args = self.generate_args_vec()?;
+                    create_node!(
+                        ASTClass::FuncCall(left, args, None),
+                        self.span_from(start)
+                    )
                 }
-                // e.g test.ok
-                else {
-                    create_node!(ASTClass::ModulePort(left, port_id))
+                TokenClass::Symbol(Symbol::LeftSquareBracket) => {
+                    self.lexer.next(true);
+                    let width_expr = self.bit_slice()?;
+                    self.check_right_square_bracket()?;
+                    create_node!(
+                        ASTClass::BitslicedExpr(left, width_expr),
+                        self.span_from(start)
+                    )
                 }
-            }
-            _ => left,
-        };
-
-        let nn_token = self.lexer.peek(true);
-        if let TokenClass::Operator(op) = nn_token.class {
-            self.lexer.next(true);
-            return create_node!(ASTClass::Expression(
-                left_node,
-                create_node!(ASTClass::Operator(op)),
-                self.expression_ast()
-            ));
+                // 16#(a), 32'(b)
+                TokenClass::Symbol(Symbol::Sharp)
+                | TokenClass::Symbol(Symbol::SingleQuote)
+                    if matches!(left.class, ASTClass::Number(_)) =>
+                {
+                    self.lexer.next(true);
+                    self.check_left_paren()?;
+                    let expr = self.expression_ast()?;
+                    self.check_right_paren()?;
+                    let kind = match n_token.class {
+                        TokenClass::Symbol(s) => s,
+                        _ => unreachable!(),
+                    };
+                    create_node!(
+                        ASTClass::Extension(kind, left, expr),
+                        self.span_from(start)
+                    )
+                }
+                TokenClass::UnaryOperator(op) if op.is_postfix() => {
+                    self.lexer.next(true);
+                    // nothing follows a postfix increment
+                    return Ok(create_node!(
+                        ASTClass::UnaryOperation(
+                            left,
+                            create_node!(ASTClass::UnaryOperator(op), n_token.span)
+                        ),
+                        self.span_from(start)
+                    ));
+                }
+                TokenClass::Symbol(Symbol::Dot) => {
+                    self.lexer.next(true);
+                    let port_id = self.generate_id_node()?;
+                    // e.g test.ok()
+                    if TokenClass::Symbol(Symbol::LeftParen)
+                        == self.lexer.peek(true).class
+                    {
+                        self.lexer.next(true);
+                        let args = self.generate_args_vec()?;
+                        create_node!(
+                            ASTClass::FuncCall(left, args, Some(port_id)),
+                            self.span_from(start)
+                        )
+                    }
+                    // e.g test.ok, r.field
+                    else {
+                        create_node!(
+                            ASTClass::ModulePort(left, port_id),
+                            self.span_from(start)
+                        )
+                    }
+                }
+                _ => return Ok(left),
+            };
         }
-        left_node
     }
 
-    fn generate_id_node(&mut self) -> Box<ASTNode> {
+    fn generate_id_node(&mut self) -> Result<Box<ASTNode>, ParseError> {
         let id_token = self.lexer.next(true);
         if let TokenClass::Identifire(id_str) = id_token.class {
-            return create_node!(ASTClass::Identifire(id_str));
+            Ok(create_node!(ASTClass::Identifire(id_str), id_token.span))
         } else {
-            unexpected_token!(id_token)
+            unexpected_token!(id_token, TokenKind::Identifire)
         }
     }
 
-    fn generate_args_vec(&mut self) -> Vec<Box<ASTNode>> {
+    // the arguments after `(` up to `)`, each of them is an expression
+    #[allow(clippy::vec_box)]
+    fn generate_args_vec(&mut self) -> Result<Vec<Box<ASTNode>>, ParseError> {
         let mut args = vec![];
+        if TokenClass::Symbol(Symbol::RightParen) == self.lexer.peek(true).class {
+            self.lexer.next(true);
+            return Ok(args);
+        }
         loop {
-            let token = self.lexer.peek(true);
+            args.push(self.expression_ast()?);
+            let token = self.lexer.next(true);
             match token.class {
-                TokenClass::Symbol(Symbol::RightParen) => {
-                    self.lexer.next(true);
-                    break;
-                }
-                TokenClass::Symbol(Symbol::Comma) => {
-                    self.lexer.next(true);
-                    continue;
-                }
-                TokenClass::Identifire(id_str) => {
-                    let expr = self.expression_ast();
-                    args.push(expr);
-                }
-                TokenClass::Number(num) => {
-                    self.lexer.next(true);
-                    args.push(create_node!(ASTClass::Number(num), token.position));
-                }
+                TokenClass::Symbol(Symbol::Comma) => continue,
+                TokenClass::Symbol(Symbol::RightParen) => break,
                 _ => {
-                    unexpected_token!(token);
+                    unexpected_token!(
+                        token,
+                        TokenKind::Symbol(Symbol::Comma),
+                        TokenKind::Symbol(Symbol::RightParen)
+                    );
                 }
             }
         }
 
-        args
+        Ok(args)
     }
 
-    fn generate_func_return(&mut self) -> Option<Box<ASTNode>> {
+    fn generate_func_return(&mut self) -> Result<Option<Box<ASTNode>>, ParseError> {
         let colon_token = self.lexer.peek(true);
 
-        return if TokenClass::Symbol(Symbol::Colon) == colon_token.class {
+        if TokenClass::Symbol(Symbol::Colon) == colon_token.class {
             self.lexer.next(true);
             let port_id = self.lexer.next(true);
 
             if let TokenClass::Identifire(id_str) = port_id.class {
-                self.check_semicolon();
-                Some(create_node!(ASTClass::Identifire(id_str), port_id.position))
+                self.check_semicolon()?;
+                Ok(Some(create_node!(
+                    ASTClass::Identifire(id_str),
+                    port_id.span
+                )))
             } else {
-                unexpected_token!(port_id);
+                unexpected_token!(port_id, TokenKind::Identifire);
             }
         } else {
-            self.check_semicolon();
-            None
-        };
+            self.check_semicolon()?;
+            Ok(None)
+        }
+    }
+
+    /*
+     * the value of #define, the tokens until the end of the line.
+     * None when the name is defined without a value.
+     */
+    fn macro_value(&mut self) -> Result<Option<MacroValue>, ParseError> {
+        let tokens = self.line_tokens()?;
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(MacroValue::new(tokens)))
     }
 
-    fn generate_string_until_nl(&mut self) -> Option<String> {
-        let mut t_list: Vec<Token> = vec![];
+    /*
+     * the rest of the line of a directive. tokens are put back together
+     * with a space where the source has any, e.g. `defined(A) && W > 8`
+     */
+    fn directive_text(&mut self) -> Result<String, ParseError> {
+        let tokens = self.line_tokens()?;
+        Ok(source_text(&tokens))
+    }
+
+    // a `//` comment at the end is left to the caller
+    fn line_tokens(&mut self) -> Result<Vec<Token>, ParseError> {
+        let mut tokens = vec![];
         loop {
-            let t = self.lexer.next(false);
+            let t = self.lexer.peek(false);
             match t.class {
-                TokenClass::Newline | TokenClass::EndOfProgram => {
-                    match t_list.last() {
-                        Some(ref t) => {
-                            let str_vec = t_list
-                                .iter()
-                                .map(|t| format!("{}", t))
-                                .collect::<Vec<String>>();
-                            let result = str_vec.join("");
-                            // セミコロンのトークンのfmt::Displayの実装は、"; "となっていて
-                            // 後ろに空白を入れているが、
-                            // 最後にセミコロンが来た場合のみ、
-                            // 後ろの空白を削除して、最後の余分な空白を消している
-                            if t.class == TokenClass::Symbol(Symbol::Semicolon) {
-                                return Some(result.trim_right().to_string());
-                            }
-                            return Some(result);
-                        }
-                        None => return None,
-                    }
-                }
-                _ => {
-                    t_list.push(t);
+                TokenClass::Newline
+                | TokenClass::EndOfProgram
+                | TokenClass::CPPStyleComment(_) => return Ok(tokens),
+                TokenClass::Error(_) => {
+                    unexpected_token!(t);
                 }
+                _ => {}
             }
+            tokens.push(self.lexer.next(false));
         }
     }
 
-    fn generate_path_node(&mut self) -> Box<ASTNode> {
+    /*
+     * a name given by #define which makes a statement by itself, e.g. `MY_REGS`
+     * what it expands to is not known, so it is kept as it is.
+     * a defined name followed by anything else is a part of a usual statement.
+     */
+    fn is_macro_statement(&mut self, id: &str) -> bool {
+        self.defines.contains(id)
+            && matches!(
+                self.lexer.peek(false).class,
+                TokenClass::Newline
+                    | TokenClass::EndOfProgram
+                    | TokenClass::CPPStyleComment(_)
+                    | TokenClass::CStyleComment(_)
+                    | TokenClass::Symbol(Symbol::Semicolon)
+                    | TokenClass::Symbol(Symbol::ClosingBrace)
+            )
+    }
+
+    fn macro_statement(&mut self, id_token: &Token) -> Box<ASTNode> {
+        let id_node = create_node!(
+            ASTClass::Identifire(id_token.class.spelling()),
+            id_token.span
+        );
+        let semicolon =
+            self.lexer.peek(false).class == TokenClass::Symbol(Symbol::Semicolon);
+        if semicolon {
+            self.lexer.next(false);
+        }
+        let span = self.span_from(id_token.span);
+        create_node!(ASTClass::MacroStatement(id_node, semicolon), span)
+    }
+
+    fn generate_path_node(&mut self) -> Result<Box<ASTNode>, ParseError> {
         let path_token = self.lexer.next(true);
         if let TokenClass::String(id_str) = path_token.class {
-            return create_node!(ASTClass::String(id_str), path_token);
+            return Ok(create_node!(ASTClass::String(id_str), path_token.span));
         }
-        unexpected_token!(path_token);
+        unexpected_token!(path_token, TokenKind::String);
     }
 
-    fn check_opening_brace(&mut self) {
-        let token = self.lexer.next(true);
-        if TokenClass::Symbol(Symbol::OpeningBrace) != token.class {
-            unexpected_token!(token);
+    /*
+     * record the error and skip the rest of the broken statement.
+     * returns the placeholder which takes the place of the statement.
+     */
+    fn recover(&mut self, e: ParseError) -> Box<ASTNode> {
+        let offending = e.token.clone();
+        // an error inside a nested block is reported again by every outer block.
+        // compared by span, the end of file is a new token each time it is read
+        let reported = self
+            .errors
+            .last()
+            .is_some_and(|last| last.token.span == offending.span);
+        if !reported {
+            self.errors.push(e);
         }
+        self.synchronize(&offending);
+        create_node!(ASTClass::Error, self.span_from(offending.span))
     }
 
-    fn check_right_square_bracket(&mut self) {
-        let token = self.lexer.next(true);
-        if TokenClass::Symbol(Symbol::RightSquareBracket) != token.class {
-            unexpected_token!(token);
+    /*
+     * skip tokens until the end of the statement, that is the next `;` or `}`
+     * at the same nesting level. the `}` which closes the enclosing block is
+     * not consumed.
+     */
+    fn synchronize(&mut self, offending: &Token) {
+        let mut depth = 0;
+        if self.lexer.peek(true).position != offending.position {
+            // the offending token has been consumed already
+            match offending.class {
+                TokenClass::Symbol(Symbol::Semicolon) => return,
+                TokenClass::Symbol(Symbol::ClosingBrace) => {
+                    self.closed_by_error = true;
+                    return;
+                }
+                TokenClass::Symbol(Symbol::OpeningBrace) => depth += 1,
+                _ => {}
+            }
+        }
+        loop {
+            let t = self.lexer.peek(true);
+            match t.class {
+                TokenClass::EndOfProgram => return,
+                TokenClass::Symbol(Symbol::Semicolon) if depth == 0 => {
+                    self.lexer.next(true);
+                    return;
+                }
+                TokenClass::Symbol(Symbol::OpeningBrace) => depth += 1,
+                TokenClass::Symbol(Symbol::ClosingBrace) => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                    if depth == 0 {
+                        self.lexer.next(true);
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.lexer.next(true);
         }
     }
 
-    fn check_left_square_bracket(&mut self) {
-        let token = self.lexer.next(true);
-        if TokenClass::Symbol(Symbol::LeftSquareBracket) != token.class {
-            unexpected_token!(token);
-        }
+    /*
+     * the span from the start of `first` to the end of the last consumed token
+     */
+    fn span_from(&self, first: Span) -> Span {
+        Span::new(first.start, self.lexer.last_end(), first.line, first.column)
     }
 
-    fn check_semicolon(&mut self) {
-        let token = self.lexer.next(true);
-        if TokenClass::Symbol(Symbol::Semicolon) != token.class {
-            unexpected_token!(token);
-        }
+    fn spanned(&self, mut node: Box<ASTNode>, first: Span) -> Box<ASTNode> {
+        node.span = self.span_from(first);
+        node
     }
 
-    fn check_left_paren(&mut self) {
+    fn check_symbol(&mut self, symbol: Symbol) -> Result<(), ParseError> {
         let token = self.lexer.next(true);
-        if TokenClass::Symbol(Symbol::LeftParen) != token.class {
-            unexpected_token!(token);
+        if TokenClass::Symbol(symbol.clone()) != token.class {
+            unexpected_token!(token, TokenKind::Symbol(symbol));
         }
+        Ok(())
     }
 
-    fn check_right_paren(&mut self) {
-        let token = self.lexer.next(true);
-        if TokenClass::Symbol(Symbol::RightParen) != token.class {
-            unexpected_token!(token);
-        }
+    fn check_opening_brace(&mut self) -> Result<(), ParseError> {
+        self.check_symbol(Symbol::OpeningBrace)
     }
 
-    fn check_colon(&mut self) {
-        let token = self.lexer.next(true);
-        if TokenClass::Symbol(Symbol::Colon) != token.class {
-            unexpected_token!(token);
-        }
+    fn check_right_square_bracket(&mut self) -> Result<(), ParseError> {
+        self.check_symbol(Symbol::RightSquareBracket)
+    }
+
+    fn check_left_square_bracket(&mut self) -> Result<(), ParseError> {
+        self.check_symbol(Symbol::LeftSquareBracket)
+    }
+
+    fn check_semicolon(&mut self) -> Result<(), ParseError> {
+        self.check_symbol(Symbol::Semicolon)
+    }
+
+    fn check_left_paren(&mut self) -> Result<(), ParseError> {
+        self.check_symbol(Symbol::LeftParen)
+    }
+
+    fn check_right_paren(&mut self) -> Result<(), ParseError> {
+        self.check_symbol(Symbol::RightParen)
+    }
+
+    fn check_colon(&mut self) -> Result<(), ParseError> {
+        self.check_symbol(Symbol::Colon)
+    }
+}
+
+/*
+ * a short description of the construct which starts with the token,
+ * used as a context of the parse error.
+ */
+fn context_of(class: &TokenClass) -> Option<String> {
+    match class {
+        TokenClass::Symbol(ref s) => match s {
+            Symbol::Wire
+            | Symbol::Reg
+            | Symbol::Mem
+            | Symbol::ProcName
+            | Symbol::StateName
+            | Symbol::LabelName
+            | Symbol::Integer
+            | Symbol::Variable
+            | Symbol::FuncSelf
+            | Symbol::Input
+            | Symbol::Output
+            | Symbol::InOut
+            | Symbol::FuncIn
+            | Symbol::FuncOut
+            | Symbol::ParamInt
+            | Symbol::ParamStr => Some(format!("`{}` declaration", s)),
+            Symbol::Func
+            | Symbol::Proc
+            | Symbol::State
+            | Symbol::Any
+            | Symbol::Alt
+            | Symbol::Seq
+            | Symbol::Generate => Some(format!("`{}` block", s)),
+            Symbol::If
+            | Symbol::For
+            | Symbol::While
+            | Symbol::Return
+            | Symbol::Goto
+            | Symbol::Finish
+            | Symbol::Invoke => Some(format!("`{}` statement", s)),
+            _ => None,
+        },
+        TokenClass::Identifire(ref id) => Some(format!("statement for `{}`", id)),
+        _ => None,
     }
 }
@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ast::*;
+use lexer::Lexer;
+use parser::{ParseError, Parser};
+
+// an include nested deeper than this is taken as a recursive include
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+#[derive(Debug)]
+pub enum PreprocessErrorKind {
+    Io(io::Error),
+    // a directive line which could not be parsed
+    Parse(Box<ParseError>),
+    IncludeNotFound(String),
+    IncludeTooDeep,
+    // #else or #endif without #ifdef, or the second #else
+    Unbalanced(String),
+    // #ifdef which is not closed until the end of the file
+    Unterminated,
+    // #error in an active region
+    ErrorDirective(String),
+    // a directive which cannot be evaluated, e.g. #if
+    Unsupported(String),
+}
+
+#[derive(Debug)]
+pub struct PreprocessError {
+    pub kind: PreprocessErrorKind,
+    pub file: PathBuf,
+    // 0 when it is not about a line
+    pub line: usize,
+}
+
+impl PreprocessError {
+    fn new(kind: PreprocessErrorKind, file: &Path, line: usize) -> PreprocessError {
+        PreprocessError {
+            kind,
+            file: file.to_path_buf(),
+            line,
+        }
+    }
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: ", self.file.display())?;
+        } else {
+            write!(f, "{}:{}: ", self.file.display(), self.line)?;
+        }
+        match self.kind {
+            PreprocessErrorKind::Io(ref e) => write!(f, "{}", e),
+            PreprocessErrorKind::Parse(ref e) => write!(f, "{}", e),
+            PreprocessErrorKind::IncludeNotFound(ref name) => {
+                write!(f, "cannot find the include file \"{}\"", name)
+            }
+            PreprocessErrorKind::IncludeTooDeep => {
+                write!(f, "#include nested too deeply, is it including itself?")
+            }
+            PreprocessErrorKind::Unbalanced(ref d) => {
+                write!(f, "#{} without a matching #ifdef", d)
+            }
+            PreprocessErrorKind::Unterminated => {
+                write!(f, "#ifdef is not closed by #endif")
+            }
+            PreprocessErrorKind::ErrorDirective(ref m) => write!(f, "#error {}", m),
+            PreprocessErrorKind::Unsupported(ref d) => {
+                write!(f, "#{} is not supported by the preprocessor", d)
+            }
+        }
+    }
+}
+
+impl error::Error for PreprocessError {}
+
+// an #ifdef or #ifndef being read
+struct Conditional {
+    // the enclosing region is active
+    parent: bool,
+    // the condition of the directive holds
+    condition: bool,
+    in_else: bool,
+    line: usize,
+}
+
+impl Conditional {
+    fn active(&self) -> bool {
+        self.parent && (self.condition != self.in_else)
+    }
+}
+
+/*
+ * expands NSL sources as the NSL preprocessor does
+ *
+ *  - #define, #undef, object-like macros are expanded
+ *  - #ifdef, #ifndef, #else, #endif
+ *  - #include "file" is searched next to the including file, then the include paths
+ *
+ * the directives are dropped from the output.
+ */
+pub struct Preprocessor {
+    defines: HashMap<String, String>,
+    include_paths: Vec<PathBuf>,
+    depth: usize,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Preprocessor {
+        Preprocessor::new()
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Preprocessor {
+        Preprocessor {
+            defines: HashMap::new(),
+            include_paths: vec![],
+            depth: 0,
+        }
+    }
+
+    pub fn add_include_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.include_paths.push(path.as_ref().to_path_buf());
+    }
+
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.defines.contains_key(name)
+    }
+
+    pub fn defined_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.defines.keys().map(|k| k.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    pub fn process_file(&mut self, path: &Path) -> Result<String, PreprocessError> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| PreprocessError::new(PreprocessErrorKind::Io(e), path, 0))?;
+        self.process_source(&source, path)
+    }
+
+    /*
+     * file is where the source comes from,
+     * it is used to find included files and in the error messages.
+     */
+    pub fn process_source(
+        &mut self,
+        source: &str,
+        file: &Path,
+    ) -> Result<String, PreprocessError> {
+        let mut out = String::new();
+        let mut conditionals: Vec<Conditional> = vec![];
+        let mut in_comment = false;
+
+        for (line_no, line) in logical_lines(source) {
+            let active = conditionals.iter().all(|c| c.active());
+            let err = |kind| PreprocessError::new(kind, file, line_no);
+
+            if in_comment || !line.trim_start().starts_with('#') {
+                let expanded = self.expand(&line, &mut in_comment, &mut vec![]);
+                if active {
+                    out.push_str(&expanded);
+                    out.push('\n');
+                }
+                continue;
+            }
+
+            let node = match parse_directive(&line) {
+                Ok(node) => node,
+                // a skipped region may have anything but the conditionals
+                Err(_) if !active => continue,
+                Err(e) => return Err(err(PreprocessErrorKind::Parse(Box::new(e)))),
+            };
+            match node.class {
+                ASTClass::MacroIfdef(ref id) | ASTClass::MacroIfndef(ref id) => {
+                    let defined = self.is_defined(&format!("{}", id));
+                    let ifdef = matches!(node.class, ASTClass::MacroIfdef(_));
+                    conditionals.push(Conditional {
+                        parent: active,
+                        condition: defined == ifdef,
+                        in_else: false,
+                        line: line_no,
+                    });
+                }
+                ASTClass::MacroElse => match conditionals.last_mut() {
+                    Some(ref mut c) if !c.in_else => c.in_else = true,
+                    _ => {
+                        return Err(err(PreprocessErrorKind::Unbalanced(
+                            "else".to_string(),
+                        )))
+                    }
+                },
+                ASTClass::MacroEndif => {
+                    conditionals.pop().ok_or_else(|| {
+                        err(PreprocessErrorKind::Unbalanced("endif".to_string()))
+                    })?;
+                }
+                // keeps #endif of a skipped #if paired
+                ASTClass::MacroIf(_) if !active => conditionals.push(Conditional {
+                    parent: false,
+                    condition: false,
+                    in_else: false,
+                    line: line_no,
+                }),
+                // the others are only read in an active region
+                _ if !active => {}
+                ASTClass::MacroIf(_) | ASTClass::MacroElif(_) => {
+                    let d = if let ASTClass::MacroIf(_) = node.class {
+                        "if"
+                    } else {
+                        "elif"
+                    };
+                    return Err(err(PreprocessErrorKind::Unsupported(d.to_string())));
+                }
+                ASTClass::MacroDefine(ref id, ref value, _) => {
+                    let value = value.as_ref().map(|v| v.to_string()).unwrap_or_default();
+                    self.defines.insert(format!("{}", id), value);
+                }
+                ASTClass::MacroUndef(ref id) => {
+                    self.defines.remove(&format!("{}", id));
+                }
+                ASTClass::MacroError(ref message) => {
+                    return Err(err(PreprocessErrorKind::ErrorDirective(
+                        message.clone(),
+                    )));
+                }
+                ASTClass::MacroInclude(ref path) => {
+                    let name = match path.class {
+                        ASTClass::String(ref s) => s.clone(),
+                        _ => format!("{}", path),
+                    };
+                    let found = self
+                        .find_include(&name, file)
+                        .ok_or_else(|| err(PreprocessErrorKind::IncludeNotFound(name)))?;
+                    if self.depth >= MAX_INCLUDE_DEPTH {
+                        return Err(err(PreprocessErrorKind::IncludeTooDeep));
+                    }
+                    self.depth += 1;
+                    let included = self.process_file(&found);
+                    self.depth -= 1;
+                    out.push_str(&included?);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(c) = conditionals.last() {
+            return Err(PreprocessError::new(
+                PreprocessErrorKind::Unterminated,
+                file,
+                c.line,
+            ));
+        }
+        Ok(out)
+    }
+
+    /*
+     * reads the #define of the file and the headers it includes, whichever
+     * branch of the conditionals they are in. nothing is evaluated nor
+     * reported, a file which cannot be read or found is passed over.
+     */
+    pub fn collect_defines(&mut self, path: &Path) {
+        self.collect_defines_in(path, &mut vec![]);
+    }
+
+    fn collect_defines_in(&mut self, path: &Path, visited: &mut Vec<PathBuf>) {
+        if visited.iter().any(|p| p == path) {
+            return;
+        }
+        visited.push(path.to_path_buf());
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(_) => return,
+        };
+        let mut in_comment = false;
+        for (_, line) in logical_lines(&source) {
+            if in_comment || !line.trim_start().starts_with('#') {
+                // only to know where a comment ends
+                self.expand(&line, &mut in_comment, &mut vec![]);
+                continue;
+            }
+            let node = match parse_directive(&line) {
+                Ok(node) => node,
+                Err(_) => continue,
+            };
+            match node.class {
+                ASTClass::MacroDefine(ref id, ref value, _) => {
+                    let value = value.as_ref().map(|v| v.to_string()).unwrap_or_default();
+                    self.defines.insert(format!("{}", id), value);
+                }
+                ASTClass::MacroInclude(ref path_node) => {
+                    let name = match path_node.class {
+                        ASTClass::String(ref s) => s.clone(),
+                        _ => format!("{}", path_node),
+                    };
+                    if let Some(found) = self.find_include(&name, path) {
+                        self.collect_defines_in(&found, visited);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn find_include(&self, name: &str, from: &Path) -> Option<PathBuf> {
+        let dir = from.parent().unwrap_or_else(|| Path::new(""));
+        Some(dir.to_path_buf())
+            .into_iter()
+            .chain(self.include_paths.iter().cloned())
+            .map(|d| d.join(name))
+            .find(|p| p.is_file())
+    }
+
+    /*
+     * replaces the defined names in the text, not in comments nor strings.
+     * a macro is not expanded again in its own value, like the C preprocessor.
+     */
+    fn expand(
+        &self,
+        text: &str,
+        in_comment: &mut bool,
+        expanding: &mut Vec<String>,
+    ) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut out = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).cloned();
+            if *in_comment {
+                if c == '*' && next == Some('/') {
+                    *in_comment = false;
+                    out.push_str("*/");
+                    i += 2;
+                } else {
+                    out.push(c);
+                    i += 1;
+                }
+                continue;
+            }
+            match c {
+                '/' if next == Some('/') => {
+                    out.extend(&chars[i..]);
+                    break;
+                }
+                '/' if next == Some('*') => {
+                    *in_comment = true;
+                    out.push_str("/*");
+                    i += 2;
+                }
+                '"' => {
+                    let start = i;
+                    i += 1;
+                    while i < chars.len() && chars[i] != '"' {
+                        i += if chars[i] == '\\' { 2 } else { 1 };
+                    }
+                    i = (i + 1).min(chars.len());
+                    out.extend(&chars[start..i]);
+                }
+                // a number may have letters, e.g. 8'hFF
+                '0'..='9' => {
+                    let start = i;
+                    while i < chars.len() && is_number_char(chars[i]) {
+                        i += 1;
+                    }
+                    out.extend(&chars[start..i]);
+                }
+                'a'..='z' | 'A'..='Z' | '_' => {
+                    let start = i;
+                    while i < chars.len()
+                        && (chars[i].is_alphanumeric() || chars[i] == '_')
+                    {
+                        i += 1;
+                    }
+                    let word: String = chars[start..i].iter().collect();
+                    match self.defines.get(&word) {
+                        Some(value) if !expanding.contains(&word) => {
+                            expanding.push(word);
+                            out.push_str(&self.expand(value, &mut false, expanding));
+                            expanding.pop();
+                        }
+                        _ => out.push_str(&word),
+                    }
+                }
+                _ => {
+                    out.push(c);
+                    i += 1;
+                }
+            }
+        }
+        out
+    }
+}
+
+fn is_number_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '\''
+}
+
+/*
+ * lines of the source, a line ending with `\` is joined with the next one.
+ * each comes with the number of its first line.
+ */
+fn logical_lines(source: &str) -> Vec<(usize, String)> {
+    let mut lines = vec![];
+    let mut pending: Option<(usize, String)> = None;
+    for (i, line) in source.lines().enumerate() {
+        let (no, mut text) = pending.take().unwrap_or((i + 1, String::new()));
+        match line.strip_suffix('\\') {
+            Some(body) => {
+                text.push_str(body);
+                text.push(' ');
+                pending = Some((no, text));
+            }
+            None => {
+                text.push_str(line);
+                lines.push((no, text));
+            }
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+fn parse_directive(line: &str) -> Result<Box<ASTNode>, ParseError> {
+    let mut b = strip_line_comment(line).as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let mut p = Parser::new(&mut l);
+    p.next_ast()
+}
+
+// a directive keeps its value up to `//`, which may be in a string
+fn strip_line_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' if prev != '\\' => in_string = !in_string,
+            '/' if !in_string && prev == '/' => return &line[..i - 1],
+            _ => {}
+        }
+        prev = c;
+    }
+    line
+}
@@ -13,6 +13,7 @@ pub enum Symbol {
     RightSquareBracket, // ]
     Semicolon,          // ;
     Colon,              // :
+    Question,           // ?
     Comma,              // ,
     Dot,                // .
     Equal,              // =
@@ -22,6 +23,8 @@ pub enum Symbol {
     InOut,              // inout
     FuncIn,             // func_in
     FuncOut,            // func_out
+    ParamInt,           // param_int
+    ParamStr,           // param_str
     FuncSelf,
     Func,  // func
     Sharp, // #
@@ -31,6 +34,7 @@ pub enum Symbol {
     Reg,         // reg
     ProcName,    // proc_name
     StateName,   // state_name
+    LabelName,   // label_name
     Mem,         // mem
     Return,      // return
     Any,         // any
@@ -49,24 +53,24 @@ pub enum Symbol {
     Finish,      // finish
     Goto,        // goto
     Simulation,  // simulation
+    Interface,   // interface
                  // TODO
                  // lack some symbols
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Operator {
-    Plus,       // +
-    Minus,      // -
-    Asterisk,   // *
-    Slash,      // /
-    ShiftLeft,  // <<
-    ShiftRight, // >>
-    And,        // &
-    Pipe,       // |
-    LogicAnd,   // &&
-    LogicOr,    // ||
-    Hat,        // ^
-    //Tilde,      // ~
+    Plus,        // +
+    Minus,       // -
+    Asterisk,    // *
+    Slash,       // /
+    ShiftLeft,   // <<
+    ShiftRight,  // >>
+    And,         // &
+    Pipe,        // |
+    LogicAnd,    // &&
+    LogicOr,     // ||
+    Hat,         // ^
     Equal,       // ==
     GreaterEq,   // >=
     LessEq,      // <=
@@ -77,9 +81,59 @@ pub enum Operator {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum UnaryOperator {
-    Increment, // ++
-    Decrement, // --
-    Not,       // !
+    Increment,    // ++
+    Decrement,    // --
+    Not,          // !
+    Tilde,        // ~
+    Minus,        // -x
+    ReductionAnd, // &x
+    ReductionOr,  // |x
+    ReductionXor, // ^x
+}
+
+impl UnaryOperator {
+    // ++ and -- are also put after the operand
+    pub fn is_postfix(&self) -> bool {
+        matches!(self, UnaryOperator::Increment | UnaryOperator::Decrement)
+    }
+}
+
+impl Operator {
+    /*
+     * binding strength of the binary operator, the higher binds tighter.
+     * the same ordering as Verilog, all of them are left associative.
+     */
+    pub fn precedence(&self) -> usize {
+        match self {
+            Operator::LogicOr => 1,
+            Operator::LogicAnd => 2,
+            Operator::Pipe => 3,
+            Operator::Hat => 4,
+            Operator::And => 5,
+            Operator::Equal | Operator::NotEqual => 6,
+            Operator::LessThan
+            | Operator::LessEq
+            | Operator::GreaterThan
+            | Operator::GreaterEq => 7,
+            Operator::ShiftLeft | Operator::ShiftRight => 8,
+            Operator::Plus | Operator::Minus => 9,
+            Operator::Asterisk | Operator::Slash => 10,
+        }
+    }
+
+    /*
+     * the unary meaning of the operator when it is put before an operand,
+     * e.g. `&x` is the reduction AND of x
+     */
+    pub fn as_unary(&self) -> Option<UnaryOperator> {
+        match self {
+            Operator::Minus => Some(UnaryOperator::Minus),
+            Operator::And => Some(UnaryOperator::ReductionAnd),
+            Operator::Pipe => Some(UnaryOperator::ReductionOr),
+            Operator::Hat => Some(UnaryOperator::ReductionXor),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Operator {
@@ -112,6 +166,11 @@ impl fmt::Display for UnaryOperator {
             UnaryOperator::Not => write!(f, "!"),
             UnaryOperator::Increment => write!(f, "++"),
             UnaryOperator::Decrement => write!(f, "--"),
+            UnaryOperator::Tilde => write!(f, "~"),
+            UnaryOperator::Minus => write!(f, "-"),
+            UnaryOperator::ReductionAnd => write!(f, "&"),
+            UnaryOperator::ReductionOr => write!(f, "|"),
+            UnaryOperator::ReductionXor => write!(f, "^"),
         }
     }
 }
@@ -123,9 +182,29 @@ pub enum Macro {
     Undef,   // #undef
     Ifdef,   // #ifdef
     Ifndef,  // #ifndef
-    //TODO
-    //     Else,    // #eles
-    Endif, // #endif
+    If,      // #if
+    Elif,    // #elif
+    Else,    // #else
+    Endif,   // #endif
+    Error,   // #error
+}
+
+impl fmt::Display for Macro {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Macro::Include => "include",
+            Macro::Define => "define",
+            Macro::Undef => "undef",
+            Macro::Ifdef => "ifdef",
+            Macro::Ifndef => "ifndef",
+            Macro::If => "if",
+            Macro::Elif => "elif",
+            Macro::Else => "else",
+            Macro::Endif => "endif",
+            Macro::Error => "error",
+        };
+        write!(f, "{}", s)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -133,9 +212,9 @@ pub enum TokenClass {
     Identifire(String),
     /*
      * HDLはいくつかの表現があり、用途によってかき分け、
-     * コードフォーマッタではいじらないのでStringで持ちそのまま出す
+     * コードフォーマッタではいじらないので綴りも持ちそのまま出す
      */
-    Number(String),
+    Number(Number),
     String(String),
     // "hello.h" 等
     Symbol(Symbol),
@@ -144,15 +223,291 @@ pub enum TokenClass {
     Macro(Macro),
     CPPStyleComment(String),
     CStyleComment(Vec<String>),
+    // the rest of a line as it is, the message of #error
+    Text(String),
     Newline,
     EndOfProgram,
+    // the lexer could not make a token from the input
+    Error(LexError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Base {
+    Binary,
+    Octal,
+    Decimal,
+    Hex,
+}
+
+impl Base {
+    fn from_char(c: char) -> Option<Base> {
+        match c.to_ascii_lowercase() {
+            'b' => Some(Base::Binary),
+            'o' => Some(Base::Octal),
+            'd' => Some(Base::Decimal),
+            'h' | 'x' => Some(Base::Hex),
+            _ => None,
+        }
+    }
+
+    pub fn radix(self) -> u32 {
+        match self {
+            Base::Binary => 2,
+            Base::Octal => 8,
+            Base::Decimal => 10,
+            Base::Hex => 16,
+        }
+    }
+}
+
+impl fmt::Display for Base {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Base::Binary => "binary",
+            Base::Octal => "octal",
+            Base::Decimal => "decimal",
+            Base::Hex => "hexadecimal",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/*
+ * a number literal in one of the NSL forms
+ *
+ *  12  1_000        unsized decimal
+ *  0x1f  0b10  0o17 unsized with a prefix
+ *  8'b1010_0101     sized, the base is one of b, o, d, h
+ *  8'sh7f           sized and signed
+ *
+ * digits has the separators removed, spelling is kept as written for the formatter.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Number {
+    pub width: Option<usize>,
+    pub signed: bool,
+    pub base: Base,
+    pub digits: String,
+    pub spelling: String,
+}
+
+impl Number {
+    pub fn parse(spelling: &str) -> Result<Number, LexError> {
+        let invalid = || LexError::InvalidNumber(spelling.to_string());
+        let mut number = Number {
+            width: None,
+            signed: false,
+            base: Base::Decimal,
+            digits: String::new(),
+            spelling: spelling.to_string(),
+        };
+
+        let body = if let Some(quote) = spelling.find('\'') {
+            let width = spelling[..quote].replace('_', "");
+            number.width = Some(width.parse().map_err(|_| invalid())?);
+            let mut rest = spelling[quote + 1..].chars();
+            let mut c = rest.next().ok_or_else(invalid)?;
+            if c == 's' || c == 'S' {
+                number.signed = true;
+                c = rest.next().ok_or_else(invalid)?;
+            }
+            // 'x' is only a prefix of unsized literals
+            if c == 'x' || c == 'X' {
+                return Err(invalid());
+            }
+            number.base = Base::from_char(c).ok_or_else(invalid)?;
+            rest.as_str()
+        } else {
+            let mut chars = spelling.chars();
+            match (chars.next(), chars.next().and_then(Base::from_char)) {
+                (Some('0'), Some(base)) if base != Base::Decimal => {
+                    number.base = base;
+                    chars.as_str()
+                }
+                _ => spelling,
+            }
+        };
+
+        for c in body.chars() {
+            if c == '_' {
+                continue;
+            }
+            if !c.is_digit(number.base.radix()) {
+                return Err(LexError::InvalidDigit(c, number.base));
+            }
+            number.digits.push(c);
+        }
+        if number.digits.is_empty() {
+            return Err(invalid());
+        }
+        Ok(number)
+    }
 }
 
-#[derive(PartialEq, Clone, Debug)]
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.spelling)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    InvalidCharacter(char),
+    InvalidDigit(char, Base),
+    InvalidNumber(String),
+    UnterminatedString,
+    UnterminatedComment,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::InvalidCharacter(c) => write!(f, "invalid character `{}`", c),
+            LexError::InvalidDigit(c, base) => {
+                write!(f, "invalid digit `{}` in {} literal", c, base)
+            }
+            LexError::InvalidNumber(s) => write!(f, "invalid number literal `{}`", s),
+            LexError::UnterminatedString => write!(f, "unterminated string literal"),
+            LexError::UnterminatedComment => write!(f, "unterminated block comment"),
+        }
+    }
+}
+
+/*
+ * kind of a token without its content.
+ * it is used to tell which tokens were acceptable when the parser fails.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Identifire,
+    Number,
+    String,
+    Symbol(Symbol),
+    Operator,
+    UnaryOperator,
+    Macro,
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Symbol::Module => "module",
+            Symbol::Declare => "declare",
+            Symbol::Struct => "struct",
+            Symbol::OpeningBrace => "{",
+            Symbol::ClosingBrace => "}",
+            Symbol::LeftParen => "(",
+            Symbol::RightParen => ")",
+            Symbol::LeftSquareBracket => "[",
+            Symbol::RightSquareBracket => "]",
+            Symbol::Semicolon => ";",
+            Symbol::Colon => ":",
+            Symbol::Question => "?",
+            Symbol::Comma => ",",
+            Symbol::Dot => ".",
+            Symbol::Equal => "=",
+            Symbol::RegAssign => ":=",
+            Symbol::Input => "input",
+            Symbol::Output => "output",
+            Symbol::InOut => "inout",
+            Symbol::FuncIn => "func_in",
+            Symbol::FuncOut => "func_out",
+            Symbol::ParamInt => "param_int",
+            Symbol::ParamStr => "param_str",
+            Symbol::FuncSelf => "func_self",
+            Symbol::Func => "func",
+            Symbol::Sharp => "#",
+            Symbol::SingleQuote => "'",
+            Symbol::Wire => "wire",
+            Symbol::Reg => "reg",
+            Symbol::ProcName => "proc_name",
+            Symbol::StateName => "state_name",
+            Symbol::LabelName => "label_name",
+            Symbol::Mem => "mem",
+            Symbol::Return => "return",
+            Symbol::Any => "any",
+            Symbol::Alt => "alt",
+            Symbol::Else => "else",
+            Symbol::State => "state",
+            Symbol::Proc => "proc",
+            Symbol::If => "if",
+            Symbol::For => "for",
+            Symbol::While => "while",
+            Symbol::Seq => "seq",
+            Symbol::Variable => "variable",
+            Symbol::Integer => "integer",
+            Symbol::Generate => "generate",
+            Symbol::Invoke => "invoke",
+            Symbol::Finish => "finish",
+            Symbol::Goto => "goto",
+            Symbol::Simulation => "simulation",
+            Symbol::Interface => "interface",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Identifire => write!(f, "identifier"),
+            TokenKind::Number => write!(f, "number"),
+            TokenKind::String => write!(f, "string"),
+            TokenKind::Symbol(ref s) => write!(f, "`{}`", s),
+            TokenKind::Operator => write!(f, "operator"),
+            TokenKind::UnaryOperator => write!(f, "unary operator"),
+            TokenKind::Macro => write!(f, "macro directive"),
+        }
+    }
+}
+
+/*
+ * a range of the source.
+ * start and end are byte offsets, line and column (1-origin, counted in
+ * characters) are the location of the start.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /*
+     * from the start of self to the end of other
+     */
+    pub fn to(&self, other: &Span) -> Span {
+        Span::new(self.start, other.end, self.line, self.column)
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Token {
     pub class: TokenClass,
     pub line: usize,
+    // sequence number of the token, see span for the location in the source
     pub position: usize,
+    pub span: Span,
+}
+
+// the span is not compared so that tokens can be written without it in tests
+impl PartialEq for Token {
+    fn eq(&self, other: &Token) -> bool {
+        self.class == other.class
+            && self.line == other.line
+            && self.position == other.position
+    }
 }
 
 impl From<(TokenClass, usize, usize)> for Token {
@@ -188,50 +543,98 @@ impl From<(Macro, usize, usize)> for Token {
 impl Token {
     pub fn new(class: TokenClass, line: usize, position: usize) -> Token {
         Token {
-            class: class,
-            line: line,
-            position: position,
+            class,
+            line,
+            position,
+            span: Span::default(),
         }
     }
 }
 
+impl TokenClass {
+    /*
+     * the text of the token as it is in the source,
+     * Display of Token adds spaces for some tokens instead
+     */
+    pub fn spelling(&self) -> String {
+        match self {
+            TokenClass::Identifire(ref id) => id.clone(),
+            TokenClass::Number(ref num) => num.spelling.clone(),
+            TokenClass::String(ref s) => format!("\"{}\"", s),
+            TokenClass::Symbol(ref s) => format!("{}", s),
+            TokenClass::Operator(ref op) => format!("{}", op),
+            TokenClass::UnaryOperator(ref op) => format!("{}", op),
+            TokenClass::Macro(ref m) => format!("{}", m),
+            TokenClass::CPPStyleComment(ref c) => format!("//{}", c),
+            TokenClass::CStyleComment(ref lines) => format!("/*{}*/", lines.join("\n")),
+            TokenClass::Text(ref text) => text.clone(),
+            TokenClass::Newline => "\n".to_string(),
+            TokenClass::EndOfProgram | TokenClass::Error(_) => String::new(),
+        }
+    }
+}
+
+/*
+ * tokens put back together as they are spelled in the source,
+ * with a space where the source has any, e.g. `defined(A) && W > 8`
+ */
+pub fn source_text(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    let mut last_end: Option<usize> = None;
+    for t in tokens {
+        if last_end.is_some_and(|end| end < t.span.start) {
+            text.push(' ');
+        }
+        text.push_str(&t.class.spelling());
+        last_end = Some(t.span.end);
+    }
+    text
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.class {
             TokenClass::Identifire(ref id) => {
-                return write!(f, "{}", id);
+                write!(f, "{}", id)
             }
             TokenClass::Number(ref num) => {
-                return write!(f, "{}", num);
+                write!(f, "{}", num)
             }
             TokenClass::String(ref st) => {
-                return write!(f, "\"{}\"", st);
+                write!(f, "\"{}\"", st)
             }
             TokenClass::Symbol(Symbol::Input) => {
-                return write!(f, "input ");
+                write!(f, "input ")
             }
             TokenClass::Symbol(Symbol::Output) => {
-                return write!(f, "output ");
+                write!(f, "output ")
             }
             TokenClass::Symbol(Symbol::FuncOut) => {
-                return write!(f, "func_out ");
+                write!(f, "func_out ")
             }
             TokenClass::Symbol(Symbol::LeftSquareBracket) => {
-                return write!(f, "[ ");
+                write!(f, "[ ")
             }
             TokenClass::Symbol(Symbol::RightSquareBracket) => {
-                return write!(f, " ]");
+                write!(f, " ]")
             }
             TokenClass::Symbol(Symbol::LeftParen) => {
-                return write!(f, "( ");
+                write!(f, "( ")
             }
             TokenClass::Symbol(Symbol::RightParen) => {
-                return write!(f, " )");
+                write!(f, " )")
             }
             TokenClass::Symbol(Symbol::Semicolon) => {
-                return write!(f, "; ");
+                write!(f, "; ")
             }
             TokenClass::Operator(ref op) => write!(f, " {} ", op),
+            // the other keywords, e.g. func_in, inout
+            TokenClass::Symbol(ref s)
+                if format!("{}", s).starts_with(char::is_alphabetic) =>
+            {
+                write!(f, "{} ", s)
+            }
+            TokenClass::Symbol(ref s) => write!(f, "{}", s),
             //TODO
             _ => {
                 panic!(
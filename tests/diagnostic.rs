@@ -0,0 +1,121 @@
+extern crate nslfmt;
+
+use nslfmt::diagnostic::*;
+use nslfmt::lexer::*;
+use nslfmt::parser::*;
+use nslfmt::token::*;
+
+#[test]
+fn render_without_color() {
+    let source = "module hello {\n    wire a[2]\n}\n";
+    let mut d = Diagnostic::new("expected `;`".to_string(), 2, 14, 1);
+    d.label = Some("expected `;`".to_string());
+
+    let ans = "error: expected `;`
+ --> hello.nsl:2:14
+  |
+2 |     wire a[2]
+  |              ^ expected `;`
+";
+    assert_eq!(d.render("hello.nsl", source, false), ans);
+}
+
+#[test]
+fn render_with_color() {
+    let d = Diagnostic::new("oops".to_string(), 1, 1, 3);
+    let out = d.render("a.nsl", "abc\n", true);
+    assert!(out.starts_with("\x1b[1;31merror\x1b[0m"));
+    assert!(out.contains("\x1b[1;31m^^^\x1b[0m"));
+}
+
+#[test]
+fn render_keeps_tabs() {
+    let d = Diagnostic::new("oops".to_string(), 1, 3, 1);
+    let out = d.render("a.nsl", "\t\tx\n", false);
+    assert!(out.ends_with("  | \t\t^\n"));
+}
+
+#[test]
+fn render_out_of_source() {
+    let d = Diagnostic::new("oops".to_string(), 5, 1, 1);
+    assert_eq!(
+        d.render("a.nsl", "", false),
+        "error: oops\n --> a.nsl:5:1\n"
+    );
+}
+
+#[test]
+fn missing_semicolon_message() {
+    let mut b = "declare ok {\n    input a[2]\n}".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let mut p = Parser::new(&mut l);
+
+    let d = Diagnostic::from_parse_error(&p.next_ast().unwrap_err());
+    assert_eq!(
+        d.message,
+        "expected `;` after `input` declaration, found `}`"
+    );
+    assert_eq!(d.label, Some("expected `;`".to_string()));
+    assert_eq!(d.line, 3);
+}
+
+#[test]
+fn expected_one_of_message() {
+    let mut b = "module hello { wire a }".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let mut p = Parser::new(&mut l);
+
+    let d = Diagnostic::from_parse_error(&p.next_ast().unwrap_err());
+    assert_eq!(
+        d.message,
+        "expected one of identifier, `,`, or `;`, found `}` in `wire` declaration"
+    );
+}
+
+#[test]
+fn lexer_error_message() {
+    let mut b = "declare ok { input a @; }".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let mut p = Parser::new(&mut l);
+
+    let e = p.next_ast().unwrap_err();
+    assert_eq!(e.kind, ParseErrorKind::Lex(LexError::InvalidCharacter('@')));
+    let d = Diagnostic::from_parse_error(&e);
+    assert_eq!(d.message, "invalid character `@` in `input` declaration");
+}
+
+#[test]
+fn invalid_digit_message() {
+    let mut b = "module m { a = 4'b1021; }".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let mut p = Parser::new(&mut l);
+
+    let e = p.next_ast().unwrap_err();
+    assert_eq!(
+        e.kind,
+        ParseErrorKind::Lex(LexError::InvalidDigit('2', Base::Binary))
+    );
+    let d = Diagnostic::from_parse_error(&e);
+    assert!(d.message.starts_with("invalid digit `2` in binary literal"));
+}
+
+#[test]
+fn invalid_literal_width() {
+    let mut b = "module m { a = 4'b1021; }".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let mut p = Parser::new(&mut l);
+
+    let d = Diagnostic::from_parse_error(&p.next_ast().unwrap_err());
+    assert_eq!(d.column, 16);
+    assert_eq!(d.width, 7);
+}
+
+#[test]
+fn end_of_file_width() {
+    let mut b = "module m {".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let mut p = Parser::new(&mut l);
+
+    let d = Diagnostic::from_parse_error(&p.next_ast().unwrap_err());
+    assert_eq!(d.width, 1);
+}
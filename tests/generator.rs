@@ -1,5 +1,7 @@
 extern crate nslfmt;
 
+use nslfmt::ast::*;
+use nslfmt::config::Config;
 use nslfmt::generator::*;
 use nslfmt::lexer::*;
 use nslfmt::parser::*;
@@ -10,7 +12,23 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 
 static CALL_COUNT: AtomicUsize = AtomicUsize::new(0);
 fn get_value_with_lock() -> usize {
-    return CALL_COUNT.fetch_add(1, Ordering::Relaxed);
+    CALL_COUNT.fetch_add(1, Ordering::Relaxed)
+}
+
+#[test]
+fn parse_error() {
+    let mut b = "module hello { wire a }".as_bytes();
+    let mut l = Lexer::new(&mut b);
+
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    let mut g = Generator::new(p, &mut io);
+    match g.output_node() {
+        Err(GenerateError::Parse(e)) => {
+            assert_eq!(e.expected.len(), 3);
+        }
+        _ => panic!("a parse error is expected"),
+    }
 }
 
 #[test]
@@ -205,6 +223,30 @@ fn reg_00() {
     assert_eq!(out, ans);
 }
 
+#[test]
+fn reg_01() {
+    let out = format("module hello { reg r[8] = {a, b}; reg q[8] = 8'(a), z = -a; }");
+    let ans = "module hello
+{
+    reg r[8] = {a, b};
+    reg q[8] = 8'(a), z = -a;
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn struct_01() {
+    let out = format("struct pixel { red[W * 2]; alpha; };");
+    let ans = "struct pixel
+{
+    red[W * 2]
+    alpha
+}
+";
+    assert_eq!(out, ans);
+}
+
 #[test]
 fn wire_00() {
     let mut b = "module hello {\n  wire ok;\n}".as_bytes();
@@ -258,7 +300,7 @@ fn func_self_00() {
 
 #[test]
 fn func_call_00() {
-    let mut b = "module hello { error(12'df3f); }".as_bytes();
+    let mut b = "module hello { error(12'hf3f); }".as_bytes();
     let mut l = Lexer::new(&mut b);
     let p = Parser::new(&mut l);
     let mut io = Cursor::new(Vec::new());
@@ -267,7 +309,19 @@ fn func_call_00() {
         g.output_node().unwrap();
     }
     let out = String::from_utf8(io.get_ref().to_vec()).unwrap();
-    let ans = "module hello\n{\n    error(12'df3f);\n}\n".to_string();
+    let ans = "module hello\n{\n    error(12'hf3f);\n}\n".to_string();
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn func_call_01() {
+    let out = format("module hello { x = sub.port.f(a); y = r.field[3:0]; }");
+    let ans = "module hello
+{
+    x = sub.port.f(a);
+    y = r.field[3:0];
+}
+";
     assert_eq!(out, ans);
 }
 
@@ -403,6 +457,167 @@ fn any_01() {
     assert_eq!(out, ans);
 }
 
+#[test]
+fn alt_00() {
+    let mut b = "module test {
+            alt {
+                a == b: { d = d + e; }
+                c: d = e;
+                f: any { g: h = i; }
+                else: j();
+            }
+        }"
+    .as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    {
+        let mut g = Generator::new(p, &mut io);
+        g.output_node().unwrap();
+    }
+    let out = String::from_utf8(io.get_ref().to_vec()).unwrap();
+    let ans = "module test
+{
+    alt
+    {
+        a == b:
+        {
+            d = d + e;
+        }
+        c: d = e;
+        f:
+            any
+            {
+                g: h = i;
+            }
+        else: j();
+    }
+}
+"
+    .to_string();
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn seq_00() {
+    let mut b = "module test { seq { a := 0; for (i=0;i<N;i++) {a := a + i;} while(a) a := a - 1; } }"
+        .as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    {
+        let mut g = Generator::new(p, &mut io);
+        g.output_node().unwrap();
+    }
+    let out = String::from_utf8(io.get_ref().to_vec()).unwrap();
+    let ans = "module test
+{
+    seq
+    {
+        a := 0;
+        for (i = 0; i < N; i++)
+        {
+            a := a + i;
+        }
+        while (a)
+            a := a - 1;
+    }
+}
+"
+    .to_string();
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn proc_00() {
+    let mut b = "module test {
+            proc_name fetch(pc), exec(  );
+            proc fetch { if (ok) { exec(); finish; } }
+            proc exec { invoke fetch(pc + 4); finish; }
+        }"
+    .as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    {
+        let mut g = Generator::new(p, &mut io);
+        g.output_node().unwrap();
+    }
+    let out = String::from_utf8(io.get_ref().to_vec()).unwrap();
+    let ans = "module test
+{
+    proc_name fetch(pc), exec();
+    proc fetch
+    {
+        if (ok)
+        {
+            exec();
+            finish;
+        }
+    }
+    proc exec
+    {
+        invoke fetch(pc + 4);
+        finish;
+    }
+}
+"
+    .to_string();
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn mem_00() {
+    let mut b = "module test { mem a[4]; mem b[4][8] = {8'h00, DEFAULT, 8'h02 | MASK, 8'h03}, c[2]; }"
+        .as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    {
+        let mut g = Generator::new(p, &mut io);
+        g.output_node().unwrap();
+    }
+    let out = String::from_utf8(io.get_ref().to_vec()).unwrap();
+    let ans = "module test
+{
+    mem a[4];
+    mem b[4][8] = {8'h00, DEFAULT, 8'h02 | MASK, 8'h03}, c[2];
+}
+"
+    .to_string();
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn mem_01() {
+    let mut b =
+        "module test { mem rom[10][4] = {0, 1, 2, 3, 4, 5, 6, 7, 8, 9}; }".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    {
+        let config = Config {
+            mem_values_per_line: 4,
+            ..Config::default()
+        };
+        let mut g = Generator::with_config(p, &mut io, config);
+        g.output_node().unwrap();
+    }
+    let out = String::from_utf8(io.get_ref().to_vec()).unwrap();
+    let ans = "module test
+{
+    mem rom[10][4] =
+    {
+        0, 1, 2, 3,
+        4, 5, 6, 7,
+        8, 9
+    };
+}
+"
+    .to_string();
+    assert_eq!(out, ans);
+}
+
 #[test]
 fn comment_01() {
     let mut b = "// hello".as_bytes();
@@ -541,3 +756,623 @@ fn expression_02() {
     .to_string();
     assert_eq!(out, ans);
 }
+
+#[test]
+fn expression_03() {
+    let mut b = "module hello {
+            a = b - (c - d);
+            a = (b - c) - d;
+            a = (a | b) & c;
+            a = a | (b & c);
+            a = (a + b) * (c << 1);
+            a = !(a && b) || c;
+            a = (a + b)[3:0];
+        }"
+    .as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    {
+        let mut g = Generator::new(p, &mut io);
+        g.output_node().unwrap();
+    }
+    let out = String::from_utf8(io.get_ref().to_vec()).unwrap();
+    let ans = "module hello
+{
+    a = b - (c - d);
+    a = (b - c) - d;
+    a = (a | b) & c;
+    a = a | (b & c);
+    a = (a + b) * (c << 1);
+    a = !(a && b) || c;
+    a = (a + b)[3:0];
+}
+"
+    .to_string();
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn concatenation_00() {
+    let out = format(
+        "module hello {
+            x = {a,b[3:0],  1'b0};
+            {carry,sum}:=a+b;
+            y = { 4 { z } };
+            w = {16 # ( a ), 32'( b + c ), {2{a, b}}};
+        }",
+    );
+    let ans = "module hello
+{
+    x = {a, b[3:0], 1'b0};
+    {carry, sum} := a + b;
+    y = {4{z}};
+    w = {16#(a), 32'(b + c), {2{a, b}}};
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn ternary_00() {
+    let out = format(
+        "module hello {
+            x = sel?a:b;
+            func f { return sel ? a : b; }
+            data_out := (state_is_fetch && mem_ready) ? mem_data_in[31:0] : alu_result_reg;
+            next_pc = branch_taken ? branch_target_address : jump ? jump_target_address : pc + 4;
+        }",
+    );
+    let ans = "module hello
+{
+    x = sel ? a : b;
+    func f
+    {
+        return sel ? a : b;
+    }
+    data_out := (state_is_fetch && mem_ready)
+        ? mem_data_in[31:0]
+        : alu_result_reg;
+    next_pc = branch_taken
+        ? branch_target_address
+        : jump
+        ? jump_target_address
+        : pc + 4;
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn unary_00() {
+    let out = format(
+        "module hello {
+            a = & b;
+            a = | b ^ ^ c;
+            a = ~ (b & c);
+            a = - b - - c;
+            a = - - b;
+            f(~ b, - a, & v);
+        }",
+    );
+    let ans = "module hello
+{
+    a = &b;
+    a = |b ^ ^c;
+    a = ~(b & c);
+    a = -b - -c;
+    a = - -b;
+    f(~b, -a, &v);
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn label_00() {
+    let out = format(
+        "module hello {
+            label_name top,done;
+            seq { top : a := a + 1; if (a == 3) goto done; goto top; done: finish; }
+        }",
+    );
+    let ans = "module hello
+{
+    label_name top, done;
+    seq
+    {
+        top:
+        a := a + 1;
+        if (a == 3)
+            goto done;
+        goto top;
+        done:
+        finish;
+    }
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn generate_00() {
+    let out = format(
+        "module regfile {
+            integer i,j;
+            variable tmp[8],  flag;
+            generate(i=0;i<8;i++){ regs[i] := tmp; }
+        }",
+    );
+    let ans = "module regfile
+{
+    integer i, j;
+    variable tmp[8], flag;
+    generate (i = 0; i < 8; i++)
+    {
+        regs[i] := tmp;
+    }
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn macro_if_00() {
+    let out = format(
+        "#if WIDTH > 8
+#elif defined(SMALL)
+#else
+#error  no width
+#endif
+",
+    );
+    let ans = "#if WIDTH > 8
+#elif defined(SMALL)
+#else
+#error no width
+#endif
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn macro_in_arms_00() {
+    let out =
+        format("module test { any {\n#ifdef DBG\n a: b = c;\n#endif\n else: d = e; } }");
+    let ans = "module test
+{
+    any
+    {
+#ifdef DBG
+        a: b = c;
+#endif
+        else: d = e;
+    }
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn macro_in_arms_01() {
+    let mut b = "module test { alt { #ifdef DBG\n a: b = c;\n #endif\n } }".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    {
+        let config = Config {
+            indent_directives: true,
+            ..Config::default()
+        };
+        let mut g = Generator::with_config(p, &mut io, config);
+        g.output_node().unwrap();
+    }
+    let out = String::from_utf8(io.get_ref().to_vec()).unwrap();
+    let ans = "module test
+{
+    alt
+    {
+        #ifdef DBG
+        a: b = c;
+        #endif
+    }
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn macro_in_block_00() {
+    let out = format(
+        "declare test {
+input a;
+#ifdef WIDE
+output  b[16];
+#else
+output b[8];
+#endif
+}
+module test {
+func_self f;
+#ifdef SIM
+func f { if (a) b = 1; }
+#endif
+}
+",
+    );
+    let ans = "declare test
+{
+    input a;
+#ifdef WIDE
+    output b[16];
+#else
+    output b[8];
+#endif
+}
+module test
+{
+    func_self f();
+#ifdef SIM
+    func f
+    {
+        if (a)
+            b = 1;
+    }
+#endif
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn macro_in_block_01() {
+    let mut b = "module test { func f { #ifndef SIM\n a = 1;\n #endif\n } }".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    {
+        let config = Config {
+            indent_directives: true,
+            ..Config::default()
+        };
+        let mut g = Generator::with_config(p, &mut io, config);
+        g.output_node().unwrap();
+    }
+    let out = String::from_utf8(io.get_ref().to_vec()).unwrap();
+    let ans = "module test
+{
+    func f
+    {
+        #ifndef SIM
+        a = 1;
+        #endif
+    }
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn macro_statement_00() {
+    let out = format(
+        "#define MY_REGS reg a;
+module test {
+  MY_REGS
+      MY_REGS;
+  func_self f;
+}
+",
+    );
+    let ans = "#define MY_REGS reg a;
+module test
+{
+    MY_REGS
+    MY_REGS;
+    func_self f();
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn header_00() {
+    let out = format_header(
+        "#ifndef CPU_NH
+#define CPU_NH
+#include \"bus.nh\"
+#define WIDTH 32
+#define ADDR_WIDTH 16
+#define RESET
+// the core
+declare cpu { input  a[WIDTH]; output addr[ADDR_WIDTH]; }
+declare bus {}
+#endif
+",
+    );
+    let ans = "#ifndef CPU_NH
+#define CPU_NH
+
+#include \"bus.nh\"
+#define WIDTH      32
+#define ADDR_WIDTH 16
+#define RESET
+
+// the core
+declare cpu
+{
+    input a[WIDTH];
+    output addr[ADDR_WIDTH];
+}
+
+declare bus
+{
+}
+
+#endif
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn header_01() {
+    // no include guard, #endif of the #ifdef does not close the file
+    let out = format_header(
+        "#ifdef SIM
+#define DEPTH 4
+#endif
+#define N 1
+declare t { input a; }
+",
+    );
+    let ans = "#ifdef SIM
+#define DEPTH 4
+#endif
+#define N 1
+
+declare t
+{
+    input a;
+}
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn define_align_00() {
+    let out = format(
+        "#define W 8
+#define ADDR_W   (W*2)
+#define DEBUG
+module test {
+#define DEPTH 4
+#define N {1'b0, a}
+  func_self f;
+}
+#define LAST 1
+",
+    );
+    let ans = "#define W      8
+#define ADDR_W (W*2)
+#define DEBUG
+module test
+{
+#define DEPTH 4
+#define N     {1'b0, a}
+    func_self f();
+}
+#define LAST 1
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn define_comment_00() {
+    let out = format(
+        "#define A 1 // a
+#define BB 2    // b
+#define DEBUG // debug
+#define C 3
+",
+    );
+    let ans = "#define A  1  // a
+#define BB 2  // b
+#define DEBUG // debug
+#define C  3
+";
+    assert_eq!(out, ans);
+}
+
+#[test]
+fn header_02() {
+    // the comments stay on the lines of their defines
+    let out = format_header(
+        "#define A 1 // a
+#define BB 2 // b
+declare t { input a; }
+",
+    );
+    let ans = "#define A  1 // a
+#define BB 2 // b
+
+declare t
+{
+    input a;
+}
+";
+    assert_eq!(out, ans);
+}
+
+fn format_header(src: &str) -> String {
+    let mut b = src.as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    {
+        let config = Config {
+            header: true,
+            ..Config::default()
+        };
+        let mut g = Generator::with_config(p, &mut io, config);
+        g.output_node().unwrap();
+    }
+    String::from_utf8(io.get_ref().to_vec()).unwrap()
+}
+
+fn format(src: &str) -> String {
+    let mut b = src.as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let p = Parser::new(&mut l);
+    let mut io = Cursor::new(Vec::new());
+    {
+        let mut g = Generator::new(p, &mut io);
+        g.output_node().unwrap();
+    }
+    String::from_utf8(io.get_ref().to_vec()).unwrap()
+}
+
+fn parse_all(src: &str) -> Vec<ASTNode> {
+    let mut b = src.as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let mut p = Parser::new(&mut l);
+    let (ast, errors) = p.parse_recovering();
+    assert!(errors.is_empty(), "{:?}", errors);
+    ast.into_iter().map(|n| *n).collect()
+}
+
+#[test]
+fn formatting_keeps_meaning() {
+    let exprs = [
+        "(a + b) * c",
+        "a + b * c",
+        "a - (b - c)",
+        "(a - b) - c",
+        "a-b-c",
+        "((a))",
+        "(a | b) & (c ^ d)",
+        "a << (b + 1) >> c",
+        "!(a && b) || !c",
+        "(a == b) != (c < d)",
+        "(x[3:0] + y[7:4]) / 2",
+        "f(a + b, c) * (d.e)",
+        "f((a + b), (c)) - g((a - b) - c)",
+    ];
+    for e in exprs.iter() {
+        let src = format!("module m {{ x = {}; }}", e);
+        let out = format(&src);
+        // the same tree means the same meaning
+        assert_eq!(parse_all(&out), parse_all(&src), "{}", e);
+        // and formatting twice changes nothing
+        assert_eq!(format(&out), out, "{}", e);
+    }
+}
+
+#[test]
+fn func_out_00() {
+    let src = "declare hello {input a[8]; output b[8]; func_out req(a) : b; func_out ack(); func_out done;}";
+    let out = format(src);
+    let ans = "declare hello
+{
+    input a[8];
+    output b[8];
+    func_out req(a) : b;
+    func_out ack();
+    func_out done();
+}
+";
+    assert_eq!(out, ans);
+    assert_eq!(format(&out), out);
+    assert_eq!(parse_all(&out), parse_all(src));
+}
+
+#[test]
+fn func_out_01() {
+    let src = "declare cpu simulation {
+        input  din[32];
+        output dout[32];
+        inout  bus[8];
+        func_in  start(din);
+        func_out read(  dout ) :din;
+        func_out write(dout, din);
+        func_in  stop() : dout;
+    }";
+    let out = format(src);
+    let ans = "declare cpu simulation
+{
+    input din[32];
+    output dout[32];
+    inout bus[8];
+    func_in start(din);
+    func_out read(dout) : din;
+    func_out write(dout, din);
+    func_in stop() : dout;
+}
+";
+    assert_eq!(out, ans);
+    assert_eq!(format(&out), out);
+    assert_eq!(parse_all(&out), parse_all(src));
+}
+
+#[test]
+fn define_with_keyword() {
+    let out = format("#define PORTS func_in go");
+    assert_eq!(out, "#define PORTS func_in go\n");
+}
+
+#[test]
+fn param_00() {
+    let src = "declare fifo {
+        param_int WIDTH = 8;
+        param_int DEPTH = WIDTH * 2;
+        param_str NAME = \"fifo\";
+        param_int MODE;
+        input din[WIDTH];
+        output dout[WIDTH];
+        func_in push(din);
+        param_int LATER = 1;
+    }";
+    let out = format(src);
+    let ans = "declare fifo
+{
+    param_int WIDTH = 8;
+    param_int DEPTH = WIDTH * 2;
+    param_str NAME = \"fifo\";
+    param_int MODE;
+    input     din[WIDTH];
+    output    dout[WIDTH];
+    func_in push(din);
+    param_int LATER = 1;
+}
+";
+    assert_eq!(out, ans);
+    assert_eq!(format(&out), out);
+    assert_eq!(parse_all(&out), parse_all(src));
+}
+
+#[test]
+fn struct_00() {
+    let src = "declare ram interface { input addr[8]; output data[8]; }
+        module test {
+            pixel wire w;
+            pixel reg r1 = 0, r2;
+            r1.red := w.green[7:4] + r2.blue;
+            r2.pos.x = 1;
+            w.alpha = f(r1.red);
+        }";
+    let out = format(src);
+    let ans = "declare ram interface
+{
+    input addr[8];
+    output data[8];
+}
+module test
+{
+    pixel wire w;
+    pixel reg r1 = 0, r2;
+    r1.red := w.green[7:4] + r2.blue;
+    r2.pos.x = 1;
+    w.alpha = f(r1.red);
+}
+";
+    assert_eq!(out, ans);
+    assert_eq!(parse_all(&out), parse_all(src));
+}
@@ -335,10 +335,7 @@ fn number() {
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::LeftSquareBracket), 1, 6)
     );
-    assert_eq!(
-        l.next(true),
-        Token::new(TokenClass::Number("12".to_string()), 1, 7)
-    );
+    assert_eq!(l.next(true), Token::new(num("12"), 1, 7));
     assert_eq!(
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::RightSquareBracket), 1, 8)
@@ -516,7 +513,7 @@ fn macro_else() {
     let mut b = "#else".as_bytes();
     let mut l = Lexer::new(&mut b);
     assert_eq!(l.next(true), Token::from((Symbol::Sharp, 1, 1)),);
-    assert_eq!(l.next(true), Token::from((Symbol::Else, 1, 2)),);
+    assert_eq!(l.next(true), Token::from((Macro::Else, 1, 2)),);
 }
 
 #[test]
@@ -534,6 +531,68 @@ fn macro_endif() {
     assert_eq!(l.next(true), Token::new(TokenClass::EndOfProgram, 1, 3));
 }
 
+#[test]
+fn line_continuation() {
+    let mut b = "a \\\nb\\c".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    assert_eq!(
+        l.next(false),
+        Token::new(TokenClass::Identifire("a".to_string()), 1, 1)
+    );
+    // no Newline token between them
+    assert_eq!(
+        l.next(false),
+        Token::new(TokenClass::Identifire("b".to_string()), 2, 2)
+    );
+    // `\` is only allowed at the end of a line
+    assert_eq!(
+        l.next(false).class,
+        TokenClass::Error(LexError::InvalidCharacter('\\'))
+    );
+}
+
+#[test]
+fn macro_directive_word() {
+    let mut b = "#else\nelse # if".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    assert_eq!(
+        l.next(true),
+        Token::new(TokenClass::Symbol(Symbol::Sharp), 1, 1)
+    );
+    assert_eq!(
+        l.next(true),
+        Token::new(TokenClass::Macro(Macro::Else), 1, 2)
+    );
+    // only the word right after `#` is a directive
+    assert_eq!(
+        l.next(true),
+        Token::new(TokenClass::Symbol(Symbol::Else), 2, 3)
+    );
+    assert_eq!(
+        l.next(true),
+        Token::new(TokenClass::Symbol(Symbol::Sharp), 2, 4)
+    );
+    assert_eq!(l.next(true), Token::new(TokenClass::Macro(Macro::If), 2, 5));
+}
+
+#[test]
+fn macro_error_text() {
+    let mut b = "#error  a $b\nc".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    l.next(true);
+    assert_eq!(
+        l.next(true),
+        Token::new(TokenClass::Macro(Macro::Error), 1, 2)
+    );
+    let t = l.next(false);
+    assert_eq!(t.class, TokenClass::Text("a $b".to_string()));
+    assert_eq!(t.span, Span::new(8, 12, 1, 9));
+    assert_eq!(
+        l.next(true),
+        Token::new(TokenClass::Identifire("c".to_string()), 2, 4)
+    );
+}
+
 #[test]
 fn macro_define() {
     let mut b = "#define HELLO (12)".as_bytes();
@@ -554,10 +613,7 @@ fn macro_define() {
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::LeftParen), 1, 4)
     );
-    assert_eq!(
-        l.next(true),
-        Token::new(TokenClass::Number("12".to_string()), 1, 5)
-    );
+    assert_eq!(l.next(true), Token::new(num("12"), 1, 5));
     assert_eq!(
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::RightParen), 1, 6)
@@ -671,10 +727,7 @@ fn comment_00() {
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::LeftSquareBracket), 3, 7)
     );
-    assert_eq!(
-        l.next(true),
-        Token::new(TokenClass::Number("12".to_string()), 3, 8)
-    );
+    assert_eq!(l.next(true), Token::new(num("12"), 3, 8));
     assert_eq!(
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::RightSquareBracket), 3, 9)
@@ -715,10 +768,7 @@ fn number_00() {
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::LeftParen), 1, 4)
     );
-    assert_eq!(
-        l.next(true),
-        Token::new(TokenClass::Number("2'b00".to_string()), 1, 5)
-    );
+    assert_eq!(l.next(true), Token::new(num("2'b00"), 1, 5));
     assert_eq!(
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::RightParen), 1, 6)
@@ -751,10 +801,7 @@ fn number_01() {
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::LeftParen), 1, 4)
     );
-    assert_eq!(
-        l.next(true),
-        Token::new(TokenClass::Number("4'hf".to_string()), 1, 5)
-    );
+    assert_eq!(l.next(true), Token::new(num("4'hf"), 1, 5));
     assert_eq!(
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::RightParen), 1, 6)
@@ -787,10 +834,7 @@ fn number_02() {
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::LeftParen), 1, 4)
     );
-    assert_eq!(
-        l.next(true),
-        Token::new(TokenClass::Number("0b1000".to_string()), 1, 5)
-    );
+    assert_eq!(l.next(true), Token::new(num("0b1000"), 1, 5));
     assert_eq!(
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::RightParen), 1, 6)
@@ -823,10 +867,7 @@ fn number_03() {
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::LeftParen), 1, 4)
     );
-    assert_eq!(
-        l.next(true),
-        Token::new(TokenClass::Number("0x1000".to_string()), 1, 5)
-    );
+    assert_eq!(l.next(true), Token::new(num("0x1000"), 1, 5));
     assert_eq!(
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::RightParen), 1, 6)
@@ -865,7 +906,7 @@ fn mutiline_comment_00() {
     let mut b = "/**/".as_bytes();
     let mut l = Lexer::new(&mut b);
 
-    let result: Vec<String> = vec![""].iter().map(|s| s.to_string()).collect();
+    let result: Vec<String> = [""].iter().map(|s| s.to_string()).collect();
 
     assert_eq!(
         l.next(true),
@@ -878,7 +919,7 @@ fn mutiline_comment_01() {
     let mut b = "/* hello */".as_bytes();
     let mut l = Lexer::new(&mut b);
 
-    let result: Vec<String> = vec![" hello "].iter().map(|s| s.to_string()).collect();
+    let result: Vec<String> = [" hello "].iter().map(|s| s.to_string()).collect();
 
     assert_eq!(
         l.next(true),
@@ -891,13 +932,13 @@ fn mutiline_comment_02() {
     let mut b = "/*hello\n*/".as_bytes();
     let mut l = Lexer::new(&mut b);
 
-    let result: Vec<String> = vec!["hello", ""].iter().map(|s| s.to_string()).collect();
+    let result: Vec<String> = ["hello", ""].iter().map(|s| s.to_string()).collect();
 
     assert_eq!(
         l.next(true),
         Token::new(TokenClass::CStyleComment(result), 1, 1)
     );
-    assert_eq!(l.next(true), Token::new(TokenClass::EndOfProgram, 1, 2));
+    assert_eq!(l.next(true), Token::new(TokenClass::EndOfProgram, 2, 2));
 }
 
 #[test]
@@ -905,7 +946,7 @@ fn mutiline_comment_03() {
     let mut b = "/*\ndata lines\n*/".as_bytes();
     let mut l = Lexer::new(&mut b);
 
-    let result: Vec<String> = vec!["", "data lines", ""]
+    let result: Vec<String> = ["", "data lines", ""]
         .iter()
         .map(|s| s.to_string())
         .collect();
@@ -914,7 +955,7 @@ fn mutiline_comment_03() {
         l.next(true),
         Token::new(TokenClass::CStyleComment(result), 1, 1)
     );
-    assert_eq!(l.next(true), Token::new(TokenClass::EndOfProgram, 1, 2));
+    assert_eq!(l.next(true), Token::new(TokenClass::EndOfProgram, 3, 2));
 }
 
 #[test]
@@ -979,10 +1020,7 @@ fn module_wire_01() {
         Token::from((TokenClass::Identifire("ok".to_string()), 1, 5))
     );
     assert_eq!(l.next(true), Token::from((Symbol::LeftSquareBracket, 1, 6)));
-    assert_eq!(
-        l.next(true),
-        Token::from((TokenClass::Number("12".to_string()), 1, 7))
-    );
+    assert_eq!(l.next(true), Token::from((num("12"), 1, 7)));
     assert_eq!(
         l.next(true),
         Token::from((Symbol::RightSquareBracket, 1, 8))
@@ -1185,10 +1223,7 @@ fn mem_00() {
         Token::from((TokenClass::Identifire("aa".to_string()), 1, 5))
     );
     assert_eq!(l.next(true), Token::from((Symbol::LeftSquareBracket, 1, 6)));
-    assert_eq!(
-        l.next(true),
-        Token::from((TokenClass::Number("12".to_string()), 1, 7))
-    );
+    assert_eq!(l.next(true), Token::from((num("12"), 1, 7)));
 
     assert_eq!(
         l.next(true),
@@ -1225,10 +1260,7 @@ fn wire_assign_00() {
         Token::from((TokenClass::Identifire("a".to_string()), 1, 7))
     );
     assert_eq!(l.next(true), Token::from((Symbol::Equal, 1, 8)));
-    assert_eq!(
-        l.next(true),
-        Token::from((TokenClass::Number("1'b1".to_string()), 1, 9))
-    );
+    assert_eq!(l.next(true), Token::from((num("1'b1"), 1, 9)));
     assert_eq!(l.next(true), Token::from((Symbol::Semicolon, 1, 10)));
     assert_eq!(l.next(true), Token::from((Symbol::ClosingBrace, 1, 11)));
     assert_eq!(l.next(true), Token::new(TokenClass::EndOfProgram, 1, 12));
@@ -1261,10 +1293,7 @@ fn plus_00() {
         Token::from((TokenClass::Identifire("a".to_string()), 1, 9))
     );
     assert_eq!(l.next(true), Token::from((Operator::Plus, 1, 10)));
-    assert_eq!(
-        l.next(true),
-        Token::from((TokenClass::Number("1'b1".to_string()), 1, 11))
-    );
+    assert_eq!(l.next(true), Token::from((num("1'b1"), 1, 11)));
     assert_eq!(l.next(true), Token::from((Symbol::Semicolon, 1, 12)));
 
     assert_eq!(l.next(true), Token::from((Symbol::ClosingBrace, 1, 13)));
@@ -1302,10 +1331,7 @@ fn reg_assignment_00() {
         Token::from((TokenClass::Identifire("a".to_string()), 1, 9))
     );
     assert_eq!(l.next(true), Token::from((Operator::Plus, 1, 10)));
-    assert_eq!(
-        l.next(true),
-        Token::from((TokenClass::Number("1'b1".to_string()), 1, 11))
-    );
+    assert_eq!(l.next(true), Token::from((num("1'b1"), 1, 11)));
     assert_eq!(
         l.next(true),
         Token::new(TokenClass::Symbol(Symbol::Semicolon), 1, 12)
@@ -1406,20 +1432,14 @@ fn gt_lt_00() {
         Token::from((TokenClass::Identifire("address".to_string()), 1, 1))
     );
     assert_eq!(l.next(true), Token::from((Operator::GreaterEq, 1, 2)));
-    assert_eq!(
-        l.next(true),
-        Token::new(TokenClass::Number("12'h3a0".to_string()), 1, 3)
-    );
+    assert_eq!(l.next(true), Token::new(num("12'h3a0"), 1, 3));
     assert_eq!(l.next(true), Token::from((Operator::LogicAnd, 1, 4)));
     assert_eq!(
         l.next(true),
         Token::from((TokenClass::Identifire("address".to_string()), 1, 5))
     );
     assert_eq!(l.next(true), Token::from((Operator::LessEq, 1, 6)));
-    assert_eq!(
-        l.next(true),
-        Token::new(TokenClass::Number("12'h3bf".to_string()), 1, 7)
-    );
+    assert_eq!(l.next(true), Token::new(num("12'h3bf"), 1, 7));
 }
 
 #[test]
@@ -1502,3 +1522,189 @@ fn struct_00() {
     let mut l = Lexer::new(&mut b);
     assert_eq!(l.next(true), Token::from((Symbol::Struct, 1, 1)));
 }
+
+#[test]
+fn invalid_character_00() {
+    let mut b = "a $ b".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    l.next(true);
+    assert_eq!(
+        l.next(true).class,
+        TokenClass::Error(LexError::InvalidCharacter('$'))
+    );
+    assert_eq!(l.next(true).class, TokenClass::Identifire("b".to_string()));
+}
+
+#[test]
+fn unterminated_string_00() {
+    let mut b = "#include \"hello.h\n".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    l.next(true);
+    l.next(true);
+    assert_eq!(
+        l.next(true).class,
+        TokenClass::Error(LexError::UnterminatedString)
+    );
+}
+
+#[test]
+fn unterminated_comment_00() {
+    let mut b = "/* hello\nworld".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    assert_eq!(
+        l.next(true).class,
+        TokenClass::Error(LexError::UnterminatedComment)
+    );
+}
+
+#[test]
+fn number_at_eof() {
+    let mut b = "12".as_bytes();
+    let mut l = Lexer::new(&mut b);
+    assert_eq!(l.next(true).class, num("12"));
+    assert_eq!(l.next(true).class, TokenClass::EndOfProgram);
+}
+
+#[test]
+fn span_00() {
+    let mut b = "declare hello {\n\tinput ok[12];\n}".as_bytes();
+    let mut l = Lexer::new(&mut b);
+
+    assert_eq!(l.next(true).span, Span::new(0, 7, 1, 1));
+    assert_eq!(l.next(true).span, Span::new(8, 13, 1, 9));
+    assert_eq!(l.next(true).span, Span::new(14, 15, 1, 15));
+    assert_eq!(l.next(true).span, Span::new(17, 22, 2, 2));
+    assert_eq!(l.next(true).span, Span::new(23, 25, 2, 8));
+    assert_eq!(l.next(true).span, Span::new(25, 26, 2, 10));
+    assert_eq!(l.next(true).span, Span::new(26, 28, 2, 11));
+    assert_eq!(l.next(true).span, Span::new(28, 29, 2, 13));
+    assert_eq!(l.next(true).span, Span::new(29, 30, 2, 14));
+    assert_eq!(l.next(true).span, Span::new(31, 32, 3, 1));
+    assert_eq!(l.next(true).span, Span::new(32, 32, 3, 2));
+}
+
+#[test]
+fn span_01() {
+    let mut b = "/* a\nb */ wire".as_bytes();
+    let mut l = Lexer::new(&mut b);
+
+    let comment = l.next(true);
+    assert_eq!(comment.line, 1);
+    assert_eq!(comment.span, Span::new(0, 9, 1, 1));
+    let wire = l.next(true);
+    assert_eq!(wire.line, 2);
+    assert_eq!(wire.span, Span::new(10, 14, 2, 6));
+}
+
+#[test]
+fn param_00() {
+    let mut b = "param_int W = 8; param_str N;".as_bytes();
+    let mut l = Lexer::new(&mut b);
+
+    assert_eq!(l.next(true), Token::from((Symbol::ParamInt, 1, 1)));
+    assert_eq!(
+        l.next(true),
+        Token::from((TokenClass::Identifire("W".to_string()), 1, 2))
+    );
+    assert_eq!(l.next(true), Token::from((Symbol::Equal, 1, 3)));
+    assert_eq!(l.next(true), Token::from((num("8"), 1, 4)));
+    assert_eq!(l.next(true), Token::from((Symbol::Semicolon, 1, 5)));
+    assert_eq!(l.next(true), Token::from((Symbol::ParamStr, 1, 6)));
+}
+
+#[test]
+fn number_04() {
+    let mut b = "8'b1010_0101 16'hFFFF 4'd9 0o17 1_000 8'sh7f".as_bytes();
+    let mut l = Lexer::new(&mut b);
+
+    let t = l.next(true);
+    assert_eq!(
+        t.class,
+        TokenClass::Number(Number {
+            width: Some(8),
+            signed: false,
+            base: Base::Binary,
+            digits: "10100101".to_string(),
+            spelling: "8'b1010_0101".to_string(),
+        })
+    );
+    assert_eq!(format!("{}", t), "8'b1010_0101");
+    assert_eq!(l.next(true).class, num("16'hFFFF"));
+    assert_eq!(l.next(true).class, num("4'd9"));
+    let t = l.next(true);
+    assert_eq!(t.class, num("0o17"));
+    if let TokenClass::Number(n) = t.class {
+        assert_eq!(
+            (n.width, n.base, n.digits),
+            (None, Base::Octal, "17".to_string())
+        );
+    }
+    let t = l.next(true);
+    if let TokenClass::Number(n) = t.class {
+        assert_eq!((n.base, n.digits), (Base::Decimal, "1000".to_string()));
+    }
+    let t = l.next(true);
+    if let TokenClass::Number(n) = t.class {
+        assert!(n.signed);
+        assert_eq!((n.width, n.base), (Some(8), Base::Hex));
+    }
+    assert_eq!(l.next(true).class, TokenClass::EndOfProgram);
+}
+
+#[test]
+fn number_05() {
+    let mut b = "4'b102; 12a 0x; 3'q1".as_bytes();
+    let mut l = Lexer::new(&mut b);
+
+    assert_eq!(
+        l.next(true).class,
+        TokenClass::Error(LexError::InvalidDigit('2', Base::Binary))
+    );
+    assert_eq!(l.next(true), Token::from((Symbol::Semicolon, 1, 2)));
+    assert_eq!(
+        l.next(true).class,
+        TokenClass::Error(LexError::InvalidDigit('a', Base::Decimal))
+    );
+    assert_eq!(
+        l.next(true).class,
+        TokenClass::Error(LexError::InvalidNumber("0x".to_string()))
+    );
+    assert_eq!(l.next(true), Token::from((Symbol::Semicolon, 1, 5)));
+    assert_eq!(
+        l.next(true).class,
+        TokenClass::Error(LexError::InvalidNumber("3'q1".to_string()))
+    );
+}
+
+fn num(s: &str) -> TokenClass {
+    TokenClass::Number(Number::parse(s).unwrap())
+}
+
+#[test]
+fn zero_extension() {
+    let mut b = "32'(a)".as_bytes();
+    let mut l = Lexer::new(&mut b);
+
+    assert_eq!(l.next(true).class, num("32"));
+    assert_eq!(l.next(true), Token::from((Symbol::SingleQuote, 1, 2)));
+    assert_eq!(l.next(true), Token::from((Symbol::LeftParen, 1, 3)));
+}
+
+#[test]
+fn question_00() {
+    let mut b = "a ? b : c".as_bytes();
+    let mut l = Lexer::new(&mut b);
+
+    l.next(true);
+    assert_eq!(l.next(true), Token::from((Symbol::Question, 1, 2)));
+    l.next(true);
+    assert_eq!(l.next(true), Token::from((Symbol::Colon, 1, 4)));
+}
+
+#[test]
+fn tilde_00() {
+    let mut b = "~a".as_bytes();
+    let mut l = Lexer::new(&mut b);
+
+    assert_eq!(l.next(true), Token::from((UnaryOperator::Tilde, 1, 1)));
+}
@@ -10,7 +10,43 @@ use nslfmt::token::*;
 
 use std::fs::File;
 use std::io::BufReader;
-use std::panic;
+
+// an identifier node
+fn id(s: &str) -> Box<ASTNode> {
+    create_node!(ASTClass::Identifire(s.to_string()))
+}
+
+// the right side of `x = <src>;` in a module
+fn expression_of(src: &str) -> Box<ASTNode> {
+    let src = format!("module m {{ x = {}; }}", src);
+    let mut b = src.as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let mut p = Parser::new(&mut l);
+
+    if let ASTClass::Module(_, block) = p.next_ast().unwrap().class {
+        if let ASTClass::Block(mut contents) = block.class {
+            if let ASTClass::Assign(_, expr) = contents.remove(0).class {
+                return expr;
+            }
+        }
+    }
+    panic!("an assignment is expected");
+}
+
+// the first statement of a module made of src
+fn statement_of(src: &str) -> Box<ASTNode> {
+    let src = format!("module m {{ {} }}", src);
+    let mut b = src.as_bytes();
+    let mut l = Lexer::new(&mut b);
+    let mut p = Parser::new(&mut l);
+
+    if let ASTClass::Module(_, block) = p.next_ast().unwrap().class {
+        if let ASTClass::Block(mut contents) = block.class {
+            return contents.remove(0);
+        }
+    }
+    panic!("a statement is expected");
+}
 
 #[cfg(test)]
 mod simple_tests {
@@ -22,7 +58,7 @@ mod simple_tests {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        assert_eq!(p.next_ast(), create_node!(ASTClass::EndOfProgram));
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::EndOfProgram));
     }
 }
 
@@ -37,7 +73,7 @@ mod declare {
         let mut p = Parser::new(&mut l);
 
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(
                 create_node!(ASTClass::Identifire("ok".to_string())),
                 create_node!(ASTClass::Block(vec![])),
@@ -53,7 +89,7 @@ mod declare {
         let mut p = Parser::new(&mut l);
 
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(
                 create_node!(ASTClass::Identifire("ok".to_string())),
                 create_node!(ASTClass::Block(vec![])),
@@ -68,16 +104,15 @@ mod declare {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
+        let interfaces = vec![create_node!(ASTClass::Input(
             create_node!(ASTClass::Identifire("a".to_string())),
             None,
-        )));
+        ))];
 
         let block = create_node!(ASTClass::Block(interfaces));
         let id = create_node!(ASTClass::Identifire("ok".to_string()));
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(id, block, false))
         );
     }
@@ -88,17 +123,16 @@ mod declare {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
+        let interfaces = vec![create_node!(ASTClass::Input(
             create_node!(ASTClass::Identifire("a".to_string())),
             Some(create_node!(ASTClass::Number("2".to_string())))
-        )));
+        ))];
 
         let block = create_node!(ASTClass::Block(interfaces));
         let id = create_node!(ASTClass::Identifire("ok".to_string()));
 
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(id, block, false))
         );
     }
@@ -111,17 +145,16 @@ mod declare {
 
         let left = create_node!(ASTClass::Identifire("OK".to_string()));
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
+        let interfaces = vec![create_node!(ASTClass::Input(
             create_node!(ASTClass::Identifire("a".to_string())),
             Some(left),
-        )));
+        ))];
 
         let id = create_node!(ASTClass::Identifire("ok".to_string()));
         let block = create_node!(ASTClass::Block(interfaces));
 
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(id, block, false))
         );
     }
@@ -134,21 +167,22 @@ mod declare {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
-            create_node!(ASTClass::Identifire("a".to_string())),
-            Some(create_node!(ASTClass::Number("2".to_string())))
-        )));
-        interfaces.push(create_node!(ASTClass::Newline));
-        interfaces.push(create_node!(ASTClass::Identifire(
-            "TEST_INTERFACES".to_string()
-        )));
-        interfaces.push(create_node!(ASTClass::Newline));
+        let interfaces = vec![
+            create_node!(ASTClass::Input(
+                create_node!(ASTClass::Identifire("a".to_string())),
+                Some(create_node!(ASTClass::Number("2".to_string())))
+            )),
+            create_node!(ASTClass::Newline),
+            create_node!(ASTClass::Identifire(
+                "TEST_INTERFACES".to_string()
+            )),
+            create_node!(ASTClass::Newline),
+        ];
 
         let block = create_node!(ASTClass::Block(interfaces));
         let id = create_node!(ASTClass::Identifire("ok".to_string()));
 
-        assert_eq!(p.next_ast(), create_node!(ASTClass::Declare(id, block)));
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::Declare(id, block)));
     }
 
     #[test]
@@ -158,29 +192,92 @@ mod declare {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
-            create_node!(ASTClass::Identifire("a".to_string())),
-            Some(create_node!(ASTClass::Number("2".to_string())))
-        )));
-        interfaces.push(create_node!(ASTClass::Newline));
-        interfaces.push(create_node!(ASTClass::Identifire(
-            "TEST_INTERFACES".to_string()
-        )));
-        interfaces.push(create_node!(ASTClass::Newline));
-        interfaces.push(create_node!(ASTClass::FuncIn(
-            create_node!(ASTClass::Identifire("ok".to_string())),
-            vec![],
-            None
-        )));
+        let interfaces = vec![
+            create_node!(ASTClass::Input(
+                create_node!(ASTClass::Identifire("a".to_string())),
+                Some(create_node!(ASTClass::Number("2".to_string())))
+            )),
+            create_node!(ASTClass::Newline),
+            create_node!(ASTClass::Identifire(
+                "TEST_INTERFACES".to_string()
+            )),
+            create_node!(ASTClass::Newline),
+            create_node!(ASTClass::FuncIn(
+                create_node!(ASTClass::Identifire("ok".to_string())),
+                vec![],
+                None
+            )),
+        ];
 
         let block = create_node!(ASTClass::Block(interfaces));
         let id = create_node!(ASTClass::Identifire("ok".to_string()));
 
-        assert_eq!(p.next_ast(), create_node!(ASTClass::Declare(id, block)));
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::Declare(id, block)));
     }
     */
 
+    #[test]
+    fn param_00() {
+        let mut b = "declare ok { param_int W = 8; param_str N = \"ok\"; param_int M; }"
+            .as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let interfaces = vec![
+            create_node!(ASTClass::Param(
+                Symbol::ParamInt,
+                create_node!(ASTClass::Identifire("W".to_string())),
+                Some(create_node!(ASTClass::Number("8".to_string())))
+            )),
+            create_node!(ASTClass::Param(
+                Symbol::ParamStr,
+                create_node!(ASTClass::Identifire("N".to_string())),
+                Some(create_node!(ASTClass::String("ok".to_string())))
+            )),
+            create_node!(ASTClass::Param(
+                Symbol::ParamInt,
+                create_node!(ASTClass::Identifire("M".to_string())),
+                None
+            )),
+        ];
+
+        let id = create_node!(ASTClass::Identifire("ok".to_string()));
+        let block = create_node!(ASTClass::Block(interfaces));
+
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::Declare(id, block, false))
+        );
+    }
+
+    #[test]
+    fn interface_00() {
+        let mut b =
+            "declare ram interface { input addr[8]; func_in read(addr); }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let interfaces = vec![
+            create_node!(ASTClass::Input(
+                create_node!(ASTClass::Identifire("addr".to_string())),
+                Some(create_node!(ASTClass::Number("8".to_string())))
+            )),
+            create_node!(ASTClass::FuncIn(
+                create_node!(ASTClass::Identifire("read".to_string())),
+                vec![create_node!(ASTClass::Identifire("addr".to_string()))],
+                None
+            )),
+        ];
+
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::Interface(
+                create_node!(ASTClass::Identifire("ram".to_string())),
+                create_node!(ASTClass::Block(interfaces))
+            ))
+        );
+    }
+
     #[test]
     fn expression_in_width_block_01() {
         let mut b = "declare ok{ input a[OK / 2]; }".as_bytes();
@@ -192,17 +289,16 @@ mod declare {
         let right = create_node!(ASTClass::Number("2".to_string()));
         let expr = create_node!(ASTClass::Expression(left, op, right));
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
+        let interfaces = vec![create_node!(ASTClass::Input(
             create_node!(ASTClass::Identifire("a".to_string())),
             Some(expr)
-        )));
+        ))];
 
         let id = create_node!(ASTClass::Identifire("ok".to_string()));
         let block = create_node!(ASTClass::Block(interfaces));
 
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(id, block, false))
         );
     }
@@ -213,27 +309,27 @@ mod declare {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let left = create_node!(ASTClass::Identifire("OK".to_string()));
-        let op = create_node!(ASTClass::Operator(Operator::Slash));
+        // (OK / 4) * 2
         let expr = create_node!(ASTClass::Expression(
+            create_node!(ASTClass::Identifire("OK".to_string())),
+            create_node!(ASTClass::Operator(Operator::Slash)),
             create_node!(ASTClass::Number("4".to_string())),
-            create_node!(ASTClass::Operator(Operator::Asterisk)),
-            create_node!(ASTClass::Number("2".to_string())),
         ));
+        let op = create_node!(ASTClass::Operator(Operator::Asterisk));
+        let right = create_node!(ASTClass::Number("2".to_string()));
 
-        let top_expr = create_node!(ASTClass::Expression(left, op, expr));
+        let top_expr = create_node!(ASTClass::Expression(expr, op, right));
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
+        let interfaces = vec![create_node!(ASTClass::Input(
             create_node!(ASTClass::Identifire("a".to_string())),
             Some(top_expr),
-        )));
+        ))];
 
         let id = create_node!(ASTClass::Identifire("ok".to_string()));
         let block = create_node!(ASTClass::Block(interfaces));
 
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(id, block, false))
         );
     }
@@ -244,18 +340,17 @@ mod declare {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Output(
+        let mut interfaces = vec![create_node!(ASTClass::Output(
             create_node!(ASTClass::Identifire("a".to_string())),
             Some(create_node!(ASTClass::Number("2".to_string())))
-        )));
+        ))];
 
         interfaces.push(create_node!(ASTClass::InOut(
             create_node!(ASTClass::Identifire("b".to_string())),
             Some(create_node!(ASTClass::Number("12".to_string())))
         )));
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(
                 create_node!(ASTClass::Identifire("ok".to_string())),
                 create_node!(ASTClass::Block(interfaces)),
@@ -271,11 +366,10 @@ mod declare {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
+        let mut interfaces = vec![create_node!(ASTClass::Input(
             create_node!(ASTClass::Identifire("a".to_string())),
             None,
-        )));
+        ))];
 
         let args = vec![create_node!(ASTClass::Identifire("a".to_string()))];
         let func = create_node!(ASTClass::FuncIn(
@@ -286,7 +380,7 @@ mod declare {
         interfaces.push(func);
 
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(
                 create_node!(ASTClass::Identifire("ok".to_string())),
                 create_node!(ASTClass::Block(interfaces)),
@@ -301,15 +395,16 @@ mod declare {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
-            create_node!(ASTClass::Identifire("a".to_string())),
-            None,
-        )));
-        interfaces.push(create_node!(ASTClass::Output(
-            create_node!(ASTClass::Identifire("c".to_string())),
-            Some(create_node!(ASTClass::Number("2".to_string())))
-        )));
+        let mut interfaces = vec![
+            create_node!(ASTClass::Input(
+                create_node!(ASTClass::Identifire("a".to_string())),
+                None,
+            )),
+            create_node!(ASTClass::Output(
+                create_node!(ASTClass::Identifire("c".to_string())),
+                Some(create_node!(ASTClass::Number("2".to_string())))
+            )),
+        ];
         let args = vec![create_node!(ASTClass::Identifire("a".to_string()))];
         let func = create_node!(ASTClass::FuncIn(
             create_node!(ASTClass::Identifire("ok".to_string())),
@@ -319,7 +414,7 @@ mod declare {
         interfaces.push(func);
 
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(
                 create_node!(ASTClass::Identifire("ok".to_string())),
                 create_node!(ASTClass::Block(interfaces)),
@@ -334,15 +429,16 @@ mod declare {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
-            create_node!(ASTClass::Identifire("a".to_string())),
-            Some(create_node!(ASTClass::Number("3".to_string())))
-        )));
-        interfaces.push(create_node!(ASTClass::Output(
-            create_node!(ASTClass::Identifire("c".to_string())),
-            Some(create_node!(ASTClass::Number("2".to_string())))
-        )));
+        let mut interfaces = vec![
+            create_node!(ASTClass::Input(
+                create_node!(ASTClass::Identifire("a".to_string())),
+                Some(create_node!(ASTClass::Number("3".to_string())))
+            )),
+            create_node!(ASTClass::Output(
+                create_node!(ASTClass::Identifire("c".to_string())),
+                Some(create_node!(ASTClass::Number("2".to_string())))
+            )),
+        ];
         let args = vec![create_node!(ASTClass::Identifire("a".to_string()))];
         let func = create_node!(ASTClass::FuncOut(
             create_node!(ASTClass::Identifire("ok".to_string())),
@@ -352,7 +448,7 @@ mod declare {
         interfaces.push(func);
 
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(
                 create_node!(ASTClass::Identifire("ok".to_string())),
                 create_node!(ASTClass::Block(interfaces)),
@@ -367,19 +463,20 @@ mod declare {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let mut interfaces = Vec::new();
-        interfaces.push(create_node!(ASTClass::Input(
-            create_node!(ASTClass::Identifire("ok".to_string())),
-            None,
-        )));
-        interfaces.push(create_node!(ASTClass::Input(
-            create_node!(ASTClass::Identifire("ggrks".to_string())),
-            None,
-        )));
-        interfaces.push(create_node!(ASTClass::Output(
-            create_node!(ASTClass::Identifire("jk".to_string())),
-            None,
-        )));
+        let mut interfaces = vec![
+            create_node!(ASTClass::Input(
+                create_node!(ASTClass::Identifire("ok".to_string())),
+                None,
+            )),
+            create_node!(ASTClass::Input(
+                create_node!(ASTClass::Identifire("ggrks".to_string())),
+                None,
+            )),
+            create_node!(ASTClass::Output(
+                create_node!(ASTClass::Identifire("jk".to_string())),
+                None,
+            )),
+        ];
 
         let args1 = vec![create_node!(ASTClass::Identifire("ok".to_string()))];
         let func1 = create_node!(ASTClass::FuncIn(
@@ -399,7 +496,7 @@ mod declare {
         interfaces.push(func2);
 
         assert_eq!(
-            p.next_ast(),
+            p.next_ast().unwrap(),
             create_node!(ASTClass::Declare(
                 create_node!(ASTClass::Identifire("hel".to_string())),
                 create_node!(ASTClass::Block(interfaces)),
@@ -419,9 +516,63 @@ mod declare {
             create_node!(ASTClass::Block(vec![])),
             true
         ));
-        assert_eq!(p.next_ast(), declare)
+        assert_eq!(p.next_ast().unwrap(), declare)
+    }
+
+    #[test]
+    fn comment_in_declare() {
+        let mut b = "declare ok { // ports\n input a; /* end */ }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let interfaces = vec![
+            create_node!(ASTClass::CPPStyleComment(" ports".to_string())),
+            create_node!(ASTClass::Input(
+                create_node!(ASTClass::Identifire("a".to_string())),
+                None
+            )),
+            create_node!(ASTClass::CStyleComment(vec![" end ".to_string()])),
+        ];
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::Declare(
+                create_node!(ASTClass::Identifire("ok".to_string())),
+                create_node!(ASTClass::Block(interfaces)),
+                false
+            ))
+        );
     }
 
+    #[test]
+    fn macro_in_declare() {
+        let mut b =
+            "declare ok { #ifdef WIDE\n input a[16];\n #else\n input a;\n #endif\n }"
+                .as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let a = || create_node!(ASTClass::Identifire("a".to_string()));
+        let interfaces = vec![
+            create_node!(ASTClass::MacroIfdef(create_node!(ASTClass::Identifire(
+                "WIDE".to_string()
+            )))),
+            create_node!(ASTClass::Input(
+                a(),
+                Some(create_node!(ASTClass::Number("16".to_string())))
+            )),
+            create_node!(ASTClass::MacroElse),
+            create_node!(ASTClass::Input(a(), None)),
+            create_node!(ASTClass::MacroEndif),
+        ];
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::Declare(
+                create_node!(ASTClass::Identifire("ok".to_string())),
+                create_node!(ASTClass::Block(interfaces)),
+                false
+            ))
+        );
+    }
 }
 
 #[cfg(test)]
@@ -441,7 +592,7 @@ mod macros {
             false
         ));
         let include = create_node!(ASTClass::MacroInclude(path));
-        assert_eq!(p.next_ast(), include);
+        assert_eq!(p.next_ast().unwrap(), include);
     }
 
     #[test]
@@ -453,7 +604,7 @@ mod macros {
         let undef = create_node!(ASTClass::MacroUndef(create_node!(
             ASTClass::Identifire("hello".to_string())
         )));
-        assert_eq!(p.next_ast(), undef);
+        assert_eq!(p.next_ast().unwrap(), undef);
     }
 
     #[test]
@@ -465,7 +616,7 @@ mod macros {
         let ifdef = create_node!(ASTClass::MacroIfdef(create_node!(
             ASTClass::Identifire("hello".to_string())
         )));
-        assert_eq!(p.next_ast(), ifdef);
+        assert_eq!(p.next_ast().unwrap(), ifdef);
     }
 
     #[test]
@@ -477,7 +628,7 @@ mod macros {
         let ifndef = create_node!(ASTClass::MacroIfndef(create_node!(
             ASTClass::Identifire("hello".to_string())
         )));
-        assert_eq!(p.next_ast(), ifndef);
+        assert_eq!(p.next_ast().unwrap(), ifndef);
     }
 
     #[test]
@@ -490,8 +641,8 @@ mod macros {
             ASTClass::Identifire("hello".to_string())
         )));
         let endif = create_node!(ASTClass::MacroEndif);
-        assert_eq!(p.next_ast(), ifndef);
-        assert_eq!(p.next_ast(), endif);
+        assert_eq!(p.next_ast().unwrap(), ifndef);
+        assert_eq!(p.next_ast().unwrap(), endif);
     }
 
     #[test]
@@ -505,9 +656,53 @@ mod macros {
         )));
         let endif = create_node!(ASTClass::MacroEndif);
         let melse = create_node!(ASTClass::MacroElse);
-        assert_eq!(p.next_ast(), ifndef);
-        assert_eq!(p.next_ast(), melse);
-        assert_eq!(p.next_ast(), endif);
+        assert_eq!(p.next_ast().unwrap(), ifndef);
+        assert_eq!(p.next_ast().unwrap(), melse);
+        assert_eq!(p.next_ast().unwrap(), endif);
+    }
+
+    #[test]
+    fn if_elif_error() {
+        let mut b = "#if defined(FAST) && WIDTH>8\n#elif  WIDTH == 8\n#else\n#error  unsupported width: WIDTH\n#endif"
+            .as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::MacroIf("defined(FAST) && WIDTH>8".to_string()))
+        );
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::MacroElif("WIDTH == 8".to_string()))
+        );
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::MacroElse));
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::MacroError("unsupported width: WIDTH".to_string()))
+        );
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::MacroEndif));
+    }
+
+    #[test]
+    fn error_free_text() {
+        // the message is not NSL
+        let mut b = "#error got $W\n#error it's 2'q broken  \n#error".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::MacroError("got $W".to_string()))
+        );
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::MacroError("it's 2'q broken".to_string()))
+        );
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::MacroError(String::new()))
+        );
     }
 
     #[test]
@@ -518,9 +713,10 @@ mod macros {
 
         let def_macro = create_node!(ASTClass::MacroDefine(
             create_node!(ASTClass::Identifire("HELLO".to_string())),
-            Some("input ok;".to_string())
+            Some(MacroValue::from("input ok;")),
+            None
         ));
-        assert_eq!(p.next_ast(), def_macro);
+        assert_eq!(p.next_ast().unwrap(), def_macro);
     }
 
     #[test]
@@ -531,9 +727,10 @@ mod macros {
 
         let def_macro = create_node!(ASTClass::MacroDefine(
             create_node!(ASTClass::Identifire("HELLO".to_string())),
-            Some("input ok;".to_string())
+            Some(MacroValue::from("input ok;")),
+            None
         ));
-        assert_eq!(p.next_ast(), def_macro);
+        assert_eq!(p.next_ast().unwrap(), def_macro);
     }
 
     #[test]
@@ -545,9 +742,65 @@ mod macros {
 
         let def_macro = create_node!(ASTClass::MacroDefine(
                 create_node!(ASTClass::Identifire("AXI4_LITE_MASTER_INTERFACE".to_string())),
-                Some("output awvalid; input awready; output awaddr[ AXI_ADDR_WIDTH ]; output awprot[ 3 ]; output wvalid; input wready; output wdata[ AXI_DATA_WIDTH ]; output wstrb[ AXI_DATA_WIDTH / 8 ]; input bvalid; output bready; input bresp[ 2 ]; output arvalid; input arready; output araddr[ AXI_ADDR_WIDTH ]; output arprot[ 3 ]; input rvalid; output rready; input rdata[ AXI_DATA_WIDTH ]; input rresp[ 2 ];".to_string())));
+                Some(MacroValue::from("output awvalid; input awready; output awaddr[AXI_ADDR_WIDTH]; output awprot[3]; output wvalid; input wready; output wdata[AXI_DATA_WIDTH]; output wstrb[AXI_DATA_WIDTH / 8]; input bvalid; output bready; input bresp[2]; output arvalid; input arready; output araddr[AXI_ADDR_WIDTH]; output arprot[3]; input rvalid; output rready; input rdata[AXI_DATA_WIDTH]; input rresp[2];")),
+                None));
+
+        assert_eq!(p.next_ast().unwrap(), def_macro);
+    }
+
+    #[test]
+    fn define_macro_lossless() {
+        // spelled as it is, tokens with no Display of their own included
+        let mut b = "#define MASK   (~8'hff&W)   // low byte\n".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        match p.next_ast().unwrap().class {
+            ASTClass::MacroDefine(_, Some(value), comment) => {
+                assert_eq!(format!("{}", value), "(~8'hff&W)");
+                assert_eq!(value.tokens.len(), 6);
+                // the comment is kept apart from the value
+                assert_eq!(comment, Some(" low byte".to_string()));
+            }
+            c => panic!("unexpected {:?}", c),
+        }
+    }
+
+    #[test]
+    fn define_macro_continued() {
+        let mut b =
+            "#define PORTS input a; \\\n    input b;\ndeclare t { PORTS }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
 
-        assert_eq!(p.next_ast(), def_macro);
+        let ports = || create_node!(ASTClass::Identifire("PORTS".to_string()));
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::MacroDefine(
+                ports(),
+                Some(MacroValue::from("input a; input b;")),
+                None
+            ))
+        );
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::Declare(
+                create_node!(ASTClass::Identifire("t".to_string())),
+                create_node!(ASTClass::Block(vec![create_node!(
+                    ASTClass::MacroStatement(ports(), false)
+                )])),
+                false
+            ))
+        );
+    }
+
+    #[test]
+    fn macro_value_eq() {
+        // where the tokens are does not matter, the spaces between them do
+        assert_eq!(MacroValue::from("a + b"), MacroValue::from("a  +   b"));
+        assert_ne!(MacroValue::from("a + b"), MacroValue::from("a+b"));
+        assert_ne!(MacroValue::from("a + b"), MacroValue::from("a - b"));
+        assert_ne!(MacroValue::from("a + b"), MacroValue::from("a + b + c"));
     }
 
     #[test]
@@ -558,9 +811,10 @@ mod macros {
 
         let def_macro = create_node!(ASTClass::MacroDefine(
             create_node!(ASTClass::Identifire("HELLO_ONLY".to_string())),
+            None,
             None
         ));
-        assert_eq!(p.next_ast(), def_macro);
+        assert_eq!(p.next_ast().unwrap(), def_macro);
     }
 }
 
@@ -580,7 +834,7 @@ mod comment {
             "".to_string(),
         ]));
 
-        assert_eq!(p.next_ast(), multi_line);
+        assert_eq!(p.next_ast().unwrap(), multi_line);
     }
 
     #[test]
@@ -590,7 +844,7 @@ mod comment {
         let mut p = Parser::new(&mut l);
 
         let one_line = create_node!(ASTClass::CPPStyleComment(" hello".to_string()));
-        assert_eq!(p.next_ast(), one_line);
+        assert_eq!(p.next_ast().unwrap(), one_line);
     }
 }
 
@@ -609,7 +863,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -629,7 +883,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -649,7 +903,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -672,7 +926,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -698,7 +952,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -725,7 +979,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -744,7 +998,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -770,7 +1024,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -796,7 +1050,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -822,7 +1076,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -848,7 +1102,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -874,7 +1128,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -893,7 +1147,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -913,7 +1167,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -941,7 +1195,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -966,7 +1220,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -991,7 +1245,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1000,15 +1254,15 @@ mod module {
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let components = vec![create_node!(ASTClass::ProcName(
+        let components = vec![create_node!(ASTClass::ProcName(vec![(
             create_node!(ASTClass::Identifire("proc_a".to_string())),
             vec![]
-        ))];
+        )]))];
         let module = create_node!(ASTClass::Module(
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1025,16 +1279,68 @@ mod module {
 
         let components = vec![
             reg_def,
-            create_node!(ASTClass::ProcName(
+            create_node!(ASTClass::ProcName(vec![(
                 create_node!(ASTClass::Identifire("proc_a".to_string())),
                 vec![create_node!(ASTClass::Identifire("r1".to_string()))]
-            )),
+            )])),
         ];
         let module = create_node!(ASTClass::Module(
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn proc_02() {
+        let mut b = "module test { proc_name proc_a(r1, r2), proc_b(); }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let components = vec![create_node!(ASTClass::ProcName(vec![
+            (
+                create_node!(ASTClass::Identifire("proc_a".to_string())),
+                vec![
+                    create_node!(ASTClass::Identifire("r1".to_string())),
+                    create_node!(ASTClass::Identifire("r2".to_string())),
+                ]
+            ),
+            (
+                create_node!(ASTClass::Identifire("proc_b".to_string())),
+                vec![]
+            ),
+        ]))];
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn proc_03() {
+        let mut b =
+            "module test { proc proc_a { invoke proc_b(r1); finish; } }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let block = create_node!(ASTClass::Block(vec![
+            create_node!(ASTClass::Invoke(create_node!(ASTClass::FuncCall(
+                create_node!(ASTClass::Identifire("proc_b".to_string())),
+                vec![create_node!(ASTClass::Identifire("r1".to_string()))],
+                None
+            )))),
+            create_node!(ASTClass::Finish),
+        ]));
+        let components = vec![create_node!(ASTClass::Proc(
+            create_node!(ASTClass::Identifire("proc_a".to_string())),
+            block
+        ))];
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1050,7 +1356,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1068,40 +1374,130 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
-    fn state_procedure_00() {
+    fn label_00() {
         let mut b =
-            "module test { state_name state1, state2; state state1 {}}".as_bytes();
+            "module test { label_name top, done; seq { top: a := 1'b1; goto top; } }"
+                .as_bytes();
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
+        let seq_block = vec![
+            create_node!(ASTClass::Label(create_node!(ASTClass::Identifire(
+                "top".to_string()
+            )))),
+            create_node!(ASTClass::RegAssign(
+                create_node!(ASTClass::Identifire("a".to_string())),
+                create_node!(ASTClass::Number("1'b1".to_string())),
+            )),
+            create_node!(ASTClass::Goto(create_node!(ASTClass::Identifire(
+                "top".to_string()
+            )))),
+        ];
         let components = vec![
-            create_node!(ASTClass::StateName(vec![
-                create_node!(ASTClass::Identifire("state1".to_string())),
-                create_node!(ASTClass::Identifire("state2".to_string())),
+            create_node!(ASTClass::LabelName(vec![
+                create_node!(ASTClass::Identifire("top".to_string())),
+                create_node!(ASTClass::Identifire("done".to_string())),
             ])),
-            create_node!(ASTClass::State(
-                create_node!(ASTClass::Identifire("state1".to_string())),
-                create_node!(ASTClass::Block(vec![]))
-            )),
+            create_node!(ASTClass::Seq(create_node!(ASTClass::Block(seq_block)))),
         ];
         let module = create_node!(ASTClass::Module(
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
-    fn state_procedure_01() {
-        let mut b = "module test { state state1 {a = 1'b1;}}".as_bytes();
+    fn goto_without_semicolon() {
+        let mut b = "module test { seq { goto top } }".as_bytes();
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let state_block = vec![create_node!(ASTClass::Assign(
+        let e = p.next_ast().unwrap_err();
+        assert_eq!(e.expected, vec![TokenKind::Symbol(Symbol::Semicolon)]);
+        assert_eq!(e.context, Some("`goto` statement".to_string()));
+    }
+
+    #[test]
+    fn generate_00() {
+        let mut b = "module test { integer i; variable v[4]; generate (i = 0; i < 4; i++) { r[i] := v; } }"
+            .as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let i = || create_node!(ASTClass::Identifire("i".to_string()));
+        let generate_block = vec![create_node!(ASTClass::RegAssign(
+            create_node!(ASTClass::BitslicedExpr(
+                create_node!(ASTClass::Identifire("r".to_string())),
+                i()
+            )),
+            create_node!(ASTClass::Identifire("v".to_string())),
+        ))];
+        let components = vec![
+            create_node!(ASTClass::Integer(vec![i()])),
+            create_node!(ASTClass::Variable(vec![(
+                create_node!(ASTClass::Identifire("v".to_string())),
+                Some(create_node!(ASTClass::Number("4".to_string())))
+            )])),
+            create_node!(ASTClass::Generate(
+                create_node!(ASTClass::Assign(
+                    i(),
+                    create_node!(ASTClass::Number("0".to_string()))
+                )),
+                create_node!(ASTClass::Expression(
+                    i(),
+                    create_node!(ASTClass::Operator(Operator::LessThan)),
+                    create_node!(ASTClass::Number("4".to_string()))
+                )),
+                create_node!(ASTClass::UnaryOperation(
+                    i(),
+                    create_node!(ASTClass::UnaryOperator(UnaryOperator::Increment))
+                )),
+                create_node!(ASTClass::Block(generate_block))
+            )),
+        ];
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn state_procedure_00() {
+        let mut b =
+            "module test { state_name state1, state2; state state1 {}}".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let components = vec![
+            create_node!(ASTClass::StateName(vec![
+                create_node!(ASTClass::Identifire("state1".to_string())),
+                create_node!(ASTClass::Identifire("state2".to_string())),
+            ])),
+            create_node!(ASTClass::State(
+                create_node!(ASTClass::Identifire("state1".to_string())),
+                create_node!(ASTClass::Block(vec![]))
+            )),
+        ];
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn state_procedure_01() {
+        let mut b = "module test { state state1 {a = 1'b1;}}".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let state_block = vec![create_node!(ASTClass::Assign(
             create_node!(ASTClass::Identifire("a".to_string())),
             create_node!(ASTClass::Number("1'b1".to_string())),
         ))];
@@ -1114,7 +1510,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1137,7 +1533,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1156,7 +1552,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(vec![mem]))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1175,7 +1571,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(vec![mem]))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1197,7 +1593,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(vec![mem]))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1215,7 +1611,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(vec![mem]))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1248,7 +1644,33 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(vec![mem]))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn mem_05() {
+        let mut b = "module test {mem aa[2][8] = {INIT_A, INIT_B + 1};}".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let mem = create_node!(ASTClass::Mem(vec![(
+            create_node!(ASTClass::Identifire("aa".to_string())),
+            create_node!(ASTClass::Number("2".to_string())),
+            Some(create_node!(ASTClass::Number("8".to_string()))),
+            Some(vec![
+                create_node!(ASTClass::Identifire("INIT_A".to_string())),
+                create_node!(ASTClass::Expression(
+                    create_node!(ASTClass::Identifire("INIT_B".to_string())),
+                    create_node!(ASTClass::Operator(Operator::Plus)),
+                    create_node!(ASTClass::Number("1".to_string())),
+                )),
+            ]),
+        )]));
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(vec![mem]))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1270,7 +1692,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1298,7 +1720,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1326,7 +1748,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1354,7 +1776,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1383,7 +1805,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1401,7 +1823,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1419,7 +1841,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1441,7 +1863,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1457,8 +1879,8 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
-        assert_eq!(p.next_ast(), create_node!(ASTClass::EndOfProgram));
+        assert_eq!(p.next_ast().unwrap(), module);
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::EndOfProgram));
     }
 
     #[test]
@@ -1470,10 +1892,12 @@ mod module {
 
         let any_comp = vec![(
             create_node!(ASTClass::Identifire("a".to_string())),
-            create_node!(ASTClass::Block(vec![create_node!(ASTClass::RegAssign(
-                create_node!(ASTClass::Identifire("a".to_string())),
-                create_node!(ASTClass::Number("1".to_string()))
-            ))])),
+            Some(create_node!(ASTClass::Block(vec![create_node!(
+                ASTClass::RegAssign(
+                    create_node!(ASTClass::Identifire("a".to_string())),
+                    create_node!(ASTClass::Number("1".to_string()))
+                )
+            )]))),
         )];
         let reg = create_node!(ASTClass::Reg(vec![(
             create_node!(ASTClass::Identifire("a".to_string())),
@@ -1486,8 +1910,8 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
-        assert_eq!(p.next_ast(), create_node!(ASTClass::EndOfProgram));
+        assert_eq!(p.next_ast().unwrap(), module);
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::EndOfProgram));
     }
 
     #[test]
@@ -1507,17 +1931,21 @@ mod module {
         let any_comp = vec![
             (
                 create_node!(ASTClass::Identifire("a".to_string())),
-                create_node!(ASTClass::Block(vec![create_node!(ASTClass::RegAssign(
-                    create_node!(ASTClass::Identifire("a".to_string())),
-                    create_node!(ASTClass::Number("1".to_string()))
-                ))])),
+                Some(create_node!(ASTClass::Block(vec![create_node!(
+                    ASTClass::RegAssign(
+                        create_node!(ASTClass::Identifire("a".to_string())),
+                        create_node!(ASTClass::Number("1".to_string()))
+                    )
+                )]))),
             ),
             (
                 expr,
-                create_node!(ASTClass::Block(vec![create_node!(ASTClass::RegAssign(
-                    create_node!(ASTClass::Identifire("a".to_string())),
-                    create_node!(ASTClass::Number("0".to_string()))
-                ))])),
+                Some(create_node!(ASTClass::Block(vec![create_node!(
+                    ASTClass::RegAssign(
+                        create_node!(ASTClass::Identifire("a".to_string())),
+                        create_node!(ASTClass::Number("0".to_string()))
+                    )
+                )]))),
             ),
         ];
         let reg = create_node!(ASTClass::Reg(vec![(
@@ -1531,7 +1959,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1547,7 +1975,7 @@ mod module {
                 create_node!(ASTClass::Operator(Operator::Equal)),
                 create_node!(ASTClass::Identifire("b".to_string())),
             )),
-            create_node!(ASTClass::Block(vec![])),
+            Some(create_node!(ASTClass::Block(vec![]))),
         )];
         let components = vec![create_node!(ASTClass::Any(any_comps))];
 
@@ -1556,7 +1984,7 @@ mod module {
             create_node!(ASTClass::Block(components))
         ));
 
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1568,11 +1996,11 @@ mod module {
         let any_comp = vec![
             (
                 create_node!(ASTClass::Identifire("a".to_string())),
-                create_node!(ASTClass::Block(vec![])),
+                Some(create_node!(ASTClass::Block(vec![]))),
             ),
             (
                 create_node!(ASTClass::Else),
-                create_node!(ASTClass::Block(vec![])),
+                Some(create_node!(ASTClass::Block(vec![]))),
             ),
         ];
         let reg = create_node!(ASTClass::Reg(vec![(
@@ -1586,7 +2014,191 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn alt_00() {
+        let mut b =
+            "module test { alt {a == b: { c = d; } e: f = g; else: h(); } }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let alt_comp = vec![
+            (
+                create_node!(ASTClass::Expression(
+                    create_node!(ASTClass::Identifire("a".to_string())),
+                    create_node!(ASTClass::Operator(Operator::Equal)),
+                    create_node!(ASTClass::Identifire("b".to_string())),
+                )),
+                Some(create_node!(ASTClass::Block(vec![create_node!(
+                    ASTClass::Assign(
+                        create_node!(ASTClass::Identifire("c".to_string())),
+                        create_node!(ASTClass::Identifire("d".to_string())),
+                    )
+                )]))),
+            ),
+            (
+                create_node!(ASTClass::Identifire("e".to_string())),
+                Some(create_node!(ASTClass::Assign(
+                    create_node!(ASTClass::Identifire("f".to_string())),
+                    create_node!(ASTClass::Identifire("g".to_string())),
+                ))),
+            ),
+            (
+                create_node!(ASTClass::Else),
+                Some(create_node!(ASTClass::FuncCall(
+                    create_node!(ASTClass::Identifire("h".to_string())),
+                    vec![],
+                    None
+                ))),
+            ),
+        ];
+        let components = vec![create_node!(ASTClass::Alt(alt_comp))];
+
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn alt_01() {
+        let mut b = "module test { alt { a: ; else: {} } }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let alt_comp = vec![
+            (
+                create_node!(ASTClass::Identifire("a".to_string())),
+                Some(create_node!(ASTClass::Block(vec![]))),
+            ),
+            (
+                create_node!(ASTClass::Else),
+                Some(create_node!(ASTClass::Block(vec![]))),
+            ),
+        ];
+        let components = vec![create_node!(ASTClass::Alt(alt_comp))];
+
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn alt_directive() {
+        let mut b = "module test { alt {\n#ifdef DBG\n a: b = c;\n#endif\n else: {} } }"
+            .as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let alt_comp = vec![
+            (create_node!(ASTClass::MacroIfdef(id("DBG"))), None),
+            (
+                id("a"),
+                Some(create_node!(ASTClass::Assign(id("b"), id("c")))),
+            ),
+            (create_node!(ASTClass::MacroEndif), None),
+            (
+                create_node!(ASTClass::Else),
+                Some(create_node!(ASTClass::Block(vec![]))),
+            ),
+        ];
+        let module = create_node!(ASTClass::Module(
+            id("test"),
+            create_node!(ASTClass::Block(vec![create_node!(ASTClass::Alt(alt_comp))]))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn seq_00() {
+        let mut b = "module test { seq { a := 1; b := 2; } }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let block = create_node!(ASTClass::Block(vec![
+            create_node!(ASTClass::RegAssign(
+                create_node!(ASTClass::Identifire("a".to_string())),
+                create_node!(ASTClass::Number("1".to_string())),
+            )),
+            create_node!(ASTClass::RegAssign(
+                create_node!(ASTClass::Identifire("b".to_string())),
+                create_node!(ASTClass::Number("2".to_string())),
+            )),
+        ]));
+        let components = vec![create_node!(ASTClass::Seq(block))];
+
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn for_00() {
+        let mut b =
+            "module test { seq { for (i = 0; i < N; i++) { a := i; } } }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let i = || create_node!(ASTClass::Identifire("i".to_string()));
+        let for_node = create_node!(ASTClass::For(
+            create_node!(ASTClass::Assign(
+                i(),
+                create_node!(ASTClass::Number("0".to_string()))
+            )),
+            create_node!(ASTClass::Expression(
+                i(),
+                create_node!(ASTClass::Operator(Operator::LessThan)),
+                create_node!(ASTClass::Identifire("N".to_string())),
+            )),
+            create_node!(ASTClass::UnaryOperation(
+                i(),
+                create_node!(ASTClass::UnaryOperator(UnaryOperator::Increment))
+            )),
+            create_node!(ASTClass::Block(vec![create_node!(ASTClass::RegAssign(
+                create_node!(ASTClass::Identifire("a".to_string())),
+                i(),
+            ))])),
+        ));
+        let components = vec![create_node!(ASTClass::Seq(create_node!(
+            ASTClass::Block(vec![for_node])
+        )))];
+
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn while_00() {
+        let mut b = "module test { seq { while (a) b := c; } }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let while_node = create_node!(ASTClass::While(
+            create_node!(ASTClass::Identifire("a".to_string())),
+            create_node!(ASTClass::RegAssign(
+                create_node!(ASTClass::Identifire("b".to_string())),
+                create_node!(ASTClass::Identifire("c".to_string())),
+            )),
+        ));
+        let components = vec![create_node!(ASTClass::Seq(create_node!(
+            ASTClass::Block(vec![while_node])
+        )))];
+
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1604,7 +2216,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1622,8 +2234,8 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
-        assert_eq!(p.next_ast(), create_node!(ASTClass::EndOfProgram));
+        assert_eq!(p.next_ast().unwrap(), module);
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::EndOfProgram));
     }
 
     #[test]
@@ -1652,7 +2264,7 @@ mod module {
             create_node!(ASTClass::Identifire("hello".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1680,7 +2292,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1699,7 +2311,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1733,7 +2345,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1753,7 +2365,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components)),
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1777,7 +2389,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components)),
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1802,7 +2414,7 @@ mod module {
             create_node!(ASTClass::Identifire("test".to_string())),
             create_node!(ASTClass::Block(components)),
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1826,7 +2438,43 @@ mod module {
             create_node!(ASTClass::Block(components))
         ));
 
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn if_04() {
+        // the form of each branch is decided by its own first token
+        let mut b =
+            "module test { if(a) b = 1; else {c = 1;} if(a) {b = 1;} else c = 1; }"
+                .as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let assign = |id: &str| {
+            create_node!(ASTClass::Assign(
+                create_node!(ASTClass::Identifire(id.to_string())),
+                create_node!(ASTClass::Number("1".to_string()))
+            ))
+        };
+        let a = || create_node!(ASTClass::Identifire("a".to_string()));
+        let components = vec![
+            create_node!(ASTClass::If(
+                a(),
+                assign("b"),
+                Some(create_node!(ASTClass::Block(vec![assign("c")])))
+            )),
+            create_node!(ASTClass::If(
+                a(),
+                create_node!(ASTClass::Block(vec![assign("b")])),
+                Some(assign("c"))
+            )),
+        ];
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1846,9 +2494,9 @@ mod module {
         ));
         let def = create_node!(ASTClass::MacroEndif);
 
-        assert_eq!(p.next_ast(), module);
-        assert_eq!(p.next_ast(), def);
-        assert_eq!(p.next_ast(), create_node!(ASTClass::EndOfProgram));
+        assert_eq!(p.next_ast().unwrap(), module);
+        assert_eq!(p.next_ast().unwrap(), def);
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::EndOfProgram));
     }
 
     #[test]
@@ -1867,9 +2515,9 @@ mod module {
             false
         ));
 
-        assert_eq!(p.next_ast(), declare);
-        assert_eq!(p.next_ast(), create_node!(ASTClass::MacroEndif));
-        assert_eq!(p.next_ast(), create_node!(ASTClass::EndOfProgram));
+        assert_eq!(p.next_ast().unwrap(), declare);
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::MacroEndif));
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::EndOfProgram));
     }
 
     #[test]
@@ -1888,9 +2536,9 @@ mod module {
             false
         ));
 
-        assert_eq!(p.next_ast(), declare);
-        assert_eq!(p.next_ast(), create_node!(ASTClass::MacroEndif));
-        assert_eq!(p.next_ast(), create_node!(ASTClass::EndOfProgram));
+        assert_eq!(p.next_ast().unwrap(), declare);
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::MacroEndif));
+        assert_eq!(p.next_ast().unwrap(), create_node!(ASTClass::EndOfProgram));
     }
 
     #[test]
@@ -1916,7 +2564,7 @@ mod module {
             ]))
         ));
 
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1941,7 +2589,7 @@ mod module {
                 ))
             ]))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1966,7 +2614,7 @@ mod module {
                 ))
             ]))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -1988,7 +2636,7 @@ mod module {
                 ))
             ]))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -2010,7 +2658,7 @@ mod module {
                 ))
             ]))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
@@ -2028,7 +2676,7 @@ mod module {
                     create_node!(ASTClass::Identifire("a".to_string()))
                 ))
             )),
-            create_node!(ASTClass::Block(vec![]))
+            Some(create_node!(ASTClass::Block(vec![])))
         ),]));
 
         let module = create_node!(ASTClass::Module(
@@ -2041,7 +2689,7 @@ mod module {
                 any
             ]))
         ));
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     /*
@@ -2072,52 +2720,135 @@ mod module {
                         ))
                 ));
 
-        assert_eq!(p.next_ast(), module);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
     */
-}
-
-#[cfg(test)]
-mod nsl_struct {
-    use super::*;
 
     #[test]
-    fn nothing() {
-        let mut b = "struct this_is_a_pen {};".as_bytes();
+    fn macro_statement_00() {
+        let mut b =
+            "#define MY_REGS reg a;\nmodule test { MY_REGS\n MY_REGS; b = MY_REGS; }"
+                .as_bytes();
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
 
-        let st = create_node!(ASTClass::Struct(
-            create_node!(ASTClass::Identifire("this_is_a_pen".to_string())),
-            vec![],
+        let regs = || create_node!(ASTClass::Identifire("MY_REGS".to_string()));
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::MacroDefine(
+                regs(),
+                Some(MacroValue::from("reg a;")),
+                None
+            ))
+        );
+        let components = vec![
+            create_node!(ASTClass::MacroStatement(regs(), false)),
+            create_node!(ASTClass::MacroStatement(regs(), true)),
+            // a defined name in an expression is left to the usual parsing
+            create_node!(ASTClass::Assign(
+                create_node!(ASTClass::Identifire("b".to_string())),
+                regs()
+            )),
+        ];
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
         ));
-
-        assert_eq!(p.next_ast(), st);
+        assert_eq!(p.next_ast().unwrap(), module);
     }
 
     #[test]
-    fn zero_bit_port_00() {
-        let mut b = "struct this_is_a_pen {
-                hello;
-            };"
-        .as_bytes();
+    fn macro_statement_01() {
+        // defined in a header which the parser does not read
+        let src = "declare test { HEADER_PORTS\n }";
+        let mut b = src.as_bytes();
         let mut l = Lexer::new(&mut b);
         let mut p = Parser::new(&mut l);
+        assert!(p.next_ast().is_err());
 
-        let st = create_node!(ASTClass::Struct(
-            create_node!(ASTClass::Identifire("this_is_a_pen".to_string())),
-            vec![(
-                create_node!(ASTClass::Identifire("hello".to_string())),
-                None
-            )],
+        let mut b = src.as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+        p.define("HEADER_PORTS");
+        let ports = create_node!(ASTClass::MacroStatement(
+            create_node!(ASTClass::Identifire("HEADER_PORTS".to_string())),
+            false
         ));
-
-        assert_eq!(p.next_ast(), st);
+        assert_eq!(
+            p.next_ast().unwrap(),
+            create_node!(ASTClass::Declare(
+                create_node!(ASTClass::Identifire("test".to_string())),
+                create_node!(ASTClass::Block(vec![ports])),
+                false
+            ))
+        );
     }
 
     #[test]
-    fn with_port_00() {
-        let mut b = "struct this_is_a_pen {
+    fn macro_in_module() {
+        let mut b = "module test { #ifndef SIM\n a = 1;\n #endif\n }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let components = vec![
+            create_node!(ASTClass::MacroIfndef(create_node!(ASTClass::Identifire(
+                "SIM".to_string()
+            )))),
+            create_node!(ASTClass::Assign(
+                create_node!(ASTClass::Identifire("a".to_string())),
+                create_node!(ASTClass::Number("1".to_string()))
+            )),
+            create_node!(ASTClass::MacroEndif),
+        ];
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+}
+
+#[cfg(test)]
+mod nsl_struct {
+    use super::*;
+
+    #[test]
+    fn nothing() {
+        let mut b = "struct this_is_a_pen {};".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let st = create_node!(ASTClass::Struct(
+            create_node!(ASTClass::Identifire("this_is_a_pen".to_string())),
+            vec![],
+        ));
+
+        assert_eq!(p.next_ast().unwrap(), st);
+    }
+
+    #[test]
+    fn zero_bit_port_00() {
+        let mut b = "struct this_is_a_pen {
+                hello;
+            };"
+        .as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let st = create_node!(ASTClass::Struct(
+            create_node!(ASTClass::Identifire("this_is_a_pen".to_string())),
+            vec![(
+                create_node!(ASTClass::Identifire("hello".to_string())),
+                None
+            )],
+        ));
+
+        assert_eq!(p.next_ast().unwrap(), st);
+    }
+
+    #[test]
+    fn with_port_00() {
+        let mut b = "struct this_is_a_pen {
                 hello[2];
             };"
         .as_bytes();
@@ -2132,6 +2863,676 @@ mod nsl_struct {
             ),]
         ));
 
-        assert_eq!(p.next_ast(), st);
+        assert_eq!(p.next_ast().unwrap(), st);
+    }
+
+    #[test]
+    fn struct_wire_reg_00() {
+        let mut b = "module test { pixel wire w; pixel reg r1 = 0, r2; }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let components = vec![
+            create_node!(ASTClass::StructWire(
+                create_node!(ASTClass::Identifire("pixel".to_string())),
+                vec![create_node!(ASTClass::Identifire("w".to_string()))]
+            )),
+            create_node!(ASTClass::StructReg(
+                create_node!(ASTClass::Identifire("pixel".to_string())),
+                vec![
+                    (
+                        create_node!(ASTClass::Identifire("r1".to_string())),
+                        Some(create_node!(ASTClass::Number("0".to_string())))
+                    ),
+                    (create_node!(ASTClass::Identifire("r2".to_string())), None),
+                ]
+            )),
+        ];
+        let module = create_node!(ASTClass::Module(
+            create_node!(ASTClass::Identifire("test".to_string())),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+
+    #[test]
+    fn struct_wire_initializer() {
+        let mut b = "module test { pixel wire w = x; }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let e = p.next_ast().unwrap_err();
+        assert_eq!(e.token.class, TokenClass::Symbol(Symbol::Equal));
+        assert_eq!(
+            e.expected,
+            vec![
+                TokenKind::Symbol(Symbol::Comma),
+                TokenKind::Symbol(Symbol::Semicolon)
+            ]
+        );
+    }
+
+    #[test]
+    fn member_access_00() {
+        let mut b = "module test { r.red := w.green[7:4]; r.pos.x = 1; }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let id = |s: &str| create_node!(ASTClass::Identifire(s.to_string()));
+        let components = vec![
+            create_node!(ASTClass::RegAssign(
+                create_node!(ASTClass::ModulePort(id("r"), id("red"))),
+                create_node!(ASTClass::BitslicedExpr(
+                    create_node!(ASTClass::ModulePort(id("w"), id("green"))),
+                    create_node!(ASTClass::BitSlice(
+                        create_node!(ASTClass::Number("7".to_string())),
+                        Some(create_node!(ASTClass::Number("4".to_string())))
+                    ))
+                ))
+            )),
+            create_node!(ASTClass::Assign(
+                create_node!(ASTClass::ModulePort(
+                    create_node!(ASTClass::ModulePort(id("r"), id("pos"))),
+                    id("x")
+                )),
+                create_node!(ASTClass::Number("1".to_string()))
+            )),
+        ];
+        let module = create_node!(ASTClass::Module(
+            id("test"),
+            create_node!(ASTClass::Block(components))
+        ));
+        assert_eq!(p.next_ast().unwrap(), module);
+    }
+}
+
+#[cfg(test)]
+mod error {
+    use super::*;
+
+    #[test]
+    fn unexpected_top_level_token() {
+        let mut b = "wire a;".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let e = p.next_ast().unwrap_err();
+        assert_eq!(e.kind, ParseErrorKind::UnexpectedToken);
+        assert_eq!(e.token.class, TokenClass::Symbol(Symbol::Wire));
+        assert_eq!(e.line, 1);
+        assert!(e.expected.contains(&TokenKind::Symbol(Symbol::Module)));
+    }
+
+    #[test]
+    fn missing_semicolon() {
+        let mut b = "declare ok {\n    input a[2]\n}".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let e = p.next_ast().unwrap_err();
+        assert_eq!(e.token.class, TokenClass::Symbol(Symbol::ClosingBrace));
+        assert_eq!(e.expected, vec![TokenKind::Symbol(Symbol::Semicolon)]);
+        assert_eq!(e.line, 3);
+    }
+}
+
+#[cfg(test)]
+mod span {
+    use super::*;
+
+    #[test]
+    fn module_span() {
+        let mut b = "module hello {\n    wire a[2];\n    a = b + c;\n}".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let ast = p.next_ast().unwrap();
+        assert_eq!(ast.span, Span::new(0, 46, 1, 1));
+        if let ASTClass::Module(ref id, ref block) = ast.class {
+            assert_eq!(id.span, Span::new(7, 12, 1, 8));
+            assert_eq!(block.span, Span::new(13, 46, 1, 14));
+            if let ASTClass::Block(ref contents) = block.class {
+                assert_eq!(contents[0].span, Span::new(19, 29, 2, 5));
+                assert_eq!(contents[1].span, Span::new(34, 44, 3, 5));
+                if let ASTClass::Assign(_, ref expr) = contents[1].class {
+                    assert_eq!(expr.span, Span::new(38, 43, 3, 9));
+                } else {
+                    panic!("assign is expected");
+                }
+            } else {
+                panic!("block is expected");
+            }
+        } else {
+            panic!("module is expected");
+        }
+    }
+
+    #[test]
+    fn error_span() {
+        let mut b = "declare ok {\n    input a[2]\n}".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let e = p.next_ast().unwrap_err();
+        assert_eq!(e.line, 3);
+        assert_eq!(e.column, 1);
+        assert_eq!(e.span(), Span::new(28, 29, 3, 1));
+    }
+}
+
+#[cfg(test)]
+mod recovery {
+    use super::*;
+
+    #[test]
+    fn broken_statements_in_module() {
+        let mut b = "module hello {\n    wire a[2];\n    a = ;\n    b = c;\n    wire d[;\n    e = f;\n}"
+            .as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let (ast, errors) = p.parse_recovering();
+        let stmts = vec![
+            create_node!(ASTClass::Wire(vec![(
+                id("a"),
+                Some(create_node!(ASTClass::Number("2".to_string())))
+            )])),
+            create_node!(ASTClass::Error),
+            create_node!(ASTClass::Assign(id("b"), id("c"))),
+            create_node!(ASTClass::Error),
+            create_node!(ASTClass::Assign(id("e"), id("f"))),
+        ];
+        assert_eq!(
+            ast,
+            vec![create_node!(ASTClass::Module(
+                id("hello"),
+                create_node!(ASTClass::Block(stmts))
+            ))]
+        );
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line, 3);
+        assert_eq!(errors[1].line, 5);
+    }
+
+    #[test]
+    fn missing_semicolon_before_closing_brace() {
+        let mut b =
+            "declare ok {\n    input a[2]\n}\nmodule ok {\n    x = ;\n}".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let (ast, errors) = p.parse_recovering();
+        assert_eq!(ast.len(), 2);
+        assert_eq!(
+            ast[0],
+            create_node!(ASTClass::Declare(
+                id("ok"),
+                create_node!(ASTClass::Block(vec![create_node!(ASTClass::Error)])),
+                false
+            ))
+        );
+        assert_eq!(
+            ast[1],
+            create_node!(ASTClass::Module(
+                id("ok"),
+                create_node!(ASTClass::Block(vec![create_node!(ASTClass::Error)]))
+            ))
+        );
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line, 3);
+        assert_eq!(errors[1].line, 5);
+    }
+
+    #[test]
+    fn nested_block() {
+        let mut b = "module m {\n    func f {\n        a = b +;\n        c = d;\n    }\n    e = g;\n}"
+            .as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let (ast, errors) = p.parse_recovering();
+        let func = create_node!(ASTClass::Func(
+            id("f"),
+            None,
+            create_node!(ASTClass::Block(vec![
+                create_node!(ASTClass::Error),
+                create_node!(ASTClass::Assign(id("c"), id("d"))),
+            ]))
+        ));
+        assert_eq!(
+            ast,
+            vec![create_node!(ASTClass::Module(
+                id("m"),
+                create_node!(ASTClass::Block(vec![
+                    func,
+                    create_node!(ASTClass::Assign(id("e"), id("g"))),
+                ]))
+            ))]
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 3);
+    }
+
+    #[test]
+    fn broken_arm_condition() {
+        let mut b = "module m {\n    any {\n        a b: x = 1;\n        c: y = 2;\n    }\n    e = f;\n}\nmodule n {}"
+            .as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let (ast, errors) = p.parse_recovering();
+        let any = create_node!(ASTClass::Any(vec![
+            (
+                create_node!(ASTClass::Error),
+                Some(create_node!(ASTClass::Block(vec![])))
+            ),
+            (
+                id("c"),
+                Some(create_node!(ASTClass::Assign(
+                    id("y"),
+                    create_node!(ASTClass::Number("2".to_string()))
+                )))
+            ),
+        ]));
+        assert_eq!(
+            ast,
+            vec![
+                create_node!(ASTClass::Module(
+                    id("m"),
+                    create_node!(ASTClass::Block(vec![
+                        any,
+                        create_node!(ASTClass::Assign(id("e"), id("f"))),
+                    ]))
+                )),
+                create_node!(ASTClass::Module(
+                    id("n"),
+                    create_node!(ASTClass::Block(vec![]))
+                )),
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 3);
+        assert_eq!(errors[0].context, Some("`any` block".to_string()));
+    }
+
+    #[test]
+    fn unclosed_arms() {
+        let mut b = "module m {\n    alt {\n        a +".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let (_, errors) = p.parse_recovering();
+        // the end of file is reported once
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].token.class, TokenClass::EndOfProgram);
+    }
+
+    #[test]
+    fn unclosed_block() {
+        let mut b = "module m {\n    func f {\n        a = b;\n".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let (_, errors) = p.parse_recovering();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].token.class, TokenClass::EndOfProgram);
+        assert_eq!(
+            errors[0].expected,
+            vec![TokenKind::Symbol(Symbol::ClosingBrace)]
+        );
+    }
+
+    #[test]
+    fn broken_top_level() {
+        let mut b = "wire a;\ndeclare ok {\n    input a;\n}".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let (ast, errors) = p.parse_recovering();
+        assert_eq!(ast.len(), 2);
+        assert_eq!(ast[0], create_node!(ASTClass::Error));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].token.class, TokenClass::Symbol(Symbol::Wire));
+    }
+}
+
+#[cfg(test)]
+mod precedence {
+    use super::*;
+
+    fn binary(left: Box<ASTNode>, op: Operator, right: Box<ASTNode>) -> Box<ASTNode> {
+        create_node!(ASTClass::Expression(
+            left,
+            create_node!(ASTClass::Operator(op)),
+            right
+        ))
+    }
+
+    #[test]
+    fn left_associative() {
+        assert_eq!(
+            expression_of("a - b - c"),
+            binary(
+                binary(id("a"), Operator::Minus, id("b")),
+                Operator::Minus,
+                id("c")
+            )
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            expression_of("a | b & c"),
+            binary(
+                id("a"),
+                Operator::Pipe,
+                binary(id("b"), Operator::And, id("c"))
+            )
+        );
+    }
+
+    #[test]
+    fn mixed() {
+        // ((a + (b * c)) == d) || (e && (f < g))
+        let sum = binary(
+            id("a"),
+            Operator::Plus,
+            binary(id("b"), Operator::Asterisk, id("c")),
+        );
+        let left = binary(sum, Operator::Equal, id("d"));
+        let right = binary(
+            id("e"),
+            Operator::LogicAnd,
+            binary(id("f"), Operator::LessThan, id("g")),
+        );
+        assert_eq!(
+            expression_of("a + b * c == d || e && f < g"),
+            binary(left, Operator::LogicOr, right)
+        );
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(
+            expression_of("a - (b - c)"),
+            binary(
+                id("a"),
+                Operator::Minus,
+                create_node!(ASTClass::Paren(binary(id("b"), Operator::Minus, id("c"))))
+            )
+        );
+        // redundant ones are kept as well
+        assert_eq!(
+            expression_of("(a * b) + c"),
+            binary(
+                create_node!(ASTClass::Paren(binary(
+                    id("a"),
+                    Operator::Asterisk,
+                    id("b")
+                ))),
+                Operator::Plus,
+                id("c")
+            )
+        );
+    }
+
+    #[test]
+    fn unary_binds_tighter() {
+        let not_a = create_node!(ASTClass::UnaryOperation(
+            create_node!(ASTClass::UnaryOperator(UnaryOperator::Not)),
+            id("a")
+        ));
+        assert_eq!(
+            expression_of("!a & b"),
+            binary(not_a, Operator::And, id("b"))
+        );
+    }
+
+    #[test]
+    fn postfix_operand() {
+        let sliced = create_node!(ASTClass::BitslicedExpr(
+            id("a"),
+            create_node!(ASTClass::BitSlice(
+                create_node!(ASTClass::Number("3".to_string())),
+                Some(create_node!(ASTClass::Number("0".to_string())))
+            ))
+        ));
+        let call = create_node!(ASTClass::FuncCall(id("f"), vec![id("b")], None));
+        assert_eq!(
+            expression_of("a[3:0] << f(b) + 1"),
+            binary(
+                sliced,
+                Operator::ShiftLeft,
+                binary(
+                    call,
+                    Operator::Plus,
+                    create_node!(ASTClass::Number("1".to_string()))
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn call_arguments() {
+        let one = create_node!(ASTClass::Number("1".to_string()));
+        let args = vec![
+            binary(one, Operator::Plus, id("b")),
+            binary(
+                id("a"),
+                Operator::Pipe,
+                binary(id("b"), Operator::And, id("c")),
+            ),
+            binary(
+                binary(id("d"), Operator::Minus, id("e")),
+                Operator::Minus,
+                id("g"),
+            ),
+        ];
+        assert_eq!(
+            expression_of("f(1 + b, a | b & c, d - e - g)"),
+            create_node!(ASTClass::FuncCall(id("f"), args, None))
+        );
+    }
+}
+
+#[cfg(test)]
+mod concatenation {
+    use super::*;
+
+    fn num(s: &str) -> Box<ASTNode> {
+        create_node!(ASTClass::Number(s.to_string()))
+    }
+
+    #[test]
+    fn right_side() {
+        assert_eq!(
+            statement_of("x = {a, b[3:0]};"),
+            create_node!(ASTClass::Assign(
+                id("x"),
+                create_node!(ASTClass::Concat(vec![
+                    id("a"),
+                    create_node!(ASTClass::BitslicedExpr(
+                        id("b"),
+                        create_node!(ASTClass::BitSlice(num("3"), Some(num("0"))))
+                    ))
+                ]))
+            ))
+        );
+    }
+
+    #[test]
+    fn left_side() {
+        assert_eq!(
+            statement_of("{carry, sum} := a + b;"),
+            create_node!(ASTClass::RegAssign(
+                create_node!(ASTClass::Concat(vec![id("carry"), id("sum")])),
+                create_node!(ASTClass::Expression(
+                    id("a"),
+                    create_node!(ASTClass::Operator(Operator::Plus)),
+                    id("b")
+                ))
+            ))
+        );
+    }
+
+    #[test]
+    fn repeat() {
+        assert_eq!(
+            statement_of("x = {4{y, 1'b0}};"),
+            create_node!(ASTClass::Assign(
+                id("x"),
+                create_node!(ASTClass::Repeat(num("4"), vec![id("y"), num("1'b0")]))
+            ))
+        );
+    }
+
+    #[test]
+    fn extension() {
+        assert_eq!(
+            statement_of("x = {16#(a), 32'(b)};"),
+            create_node!(ASTClass::Assign(
+                id("x"),
+                create_node!(ASTClass::Concat(vec![
+                    create_node!(ASTClass::Extension(Symbol::Sharp, num("16"), id("a"))),
+                    create_node!(ASTClass::Extension(
+                        Symbol::SingleQuote,
+                        num("32"),
+                        id("b")
+                    )),
+                ]))
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_assignment() {
+        let mut b = "module m { {a, b}; }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let e = p.next_ast().unwrap_err();
+        assert_eq!(
+            e.expected,
+            vec![
+                TokenKind::Symbol(Symbol::Equal),
+                TokenKind::Symbol(Symbol::RegAssign)
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod ternary {
+    use super::*;
+
+    #[test]
+    fn lowest_precedence() {
+        assert_eq!(
+            expression_of("a || b ? c + d : e"),
+            create_node!(ASTClass::Ternary(
+                create_node!(ASTClass::Expression(
+                    id("a"),
+                    create_node!(ASTClass::Operator(Operator::LogicOr)),
+                    id("b")
+                )),
+                create_node!(ASTClass::Expression(
+                    id("c"),
+                    create_node!(ASTClass::Operator(Operator::Plus)),
+                    id("d")
+                )),
+                id("e")
+            ))
+        );
+    }
+
+    #[test]
+    fn right_associative() {
+        assert_eq!(
+            expression_of("a ? b : c ? d : e"),
+            create_node!(ASTClass::Ternary(
+                id("a"),
+                id("b"),
+                create_node!(ASTClass::Ternary(id("c"), id("d"), id("e")))
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_colon() {
+        let mut b = "module m { x = a ? b; }".as_bytes();
+        let mut l = Lexer::new(&mut b);
+        let mut p = Parser::new(&mut l);
+
+        let e = p.next_ast().unwrap_err();
+        assert_eq!(e.expected, vec![TokenKind::Symbol(Symbol::Colon)]);
+    }
+}
+
+#[cfg(test)]
+mod unary {
+    use super::*;
+
+    fn unary(op: UnaryOperator, operand: Box<ASTNode>) -> Box<ASTNode> {
+        create_node!(ASTClass::UnaryOperation(
+            create_node!(ASTClass::UnaryOperator(op)),
+            operand
+        ))
+    }
+
+    #[test]
+    fn reduction() {
+        assert_eq!(
+            expression_of("&a"),
+            unary(UnaryOperator::ReductionAnd, id("a"))
+        );
+        assert_eq!(
+            expression_of("|a"),
+            unary(UnaryOperator::ReductionOr, id("a"))
+        );
+        assert_eq!(
+            expression_of("^a"),
+            unary(UnaryOperator::ReductionXor, id("a"))
+        );
+        assert_eq!(expression_of("~a"), unary(UnaryOperator::Tilde, id("a")));
+        assert_eq!(expression_of("-a"), unary(UnaryOperator::Minus, id("a")));
+    }
+
+    #[test]
+    fn by_position() {
+        // a & (&b)
+        assert_eq!(
+            expression_of("a & &b"),
+            create_node!(ASTClass::Expression(
+                id("a"),
+                create_node!(ASTClass::Operator(Operator::And)),
+                unary(UnaryOperator::ReductionAnd, id("b"))
+            ))
+        );
+        // (-a) - (~|b)
+        assert_eq!(
+            expression_of("-a - ~|b"),
+            create_node!(ASTClass::Expression(
+                unary(UnaryOperator::Minus, id("a")),
+                create_node!(ASTClass::Operator(Operator::Minus)),
+                unary(
+                    UnaryOperator::Tilde,
+                    unary(UnaryOperator::ReductionOr, id("b"))
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn in_call_arguments() {
+        let args = vec![
+            unary(UnaryOperator::Tilde, id("b")),
+            unary(UnaryOperator::Minus, id("a")),
+            unary(UnaryOperator::ReductionAnd, id("v")),
+        ];
+        assert_eq!(
+            expression_of("f(~b, -a, &v)"),
+            create_node!(ASTClass::FuncCall(id("f"), args, None))
+        );
     }
 }
@@ -0,0 +1,181 @@
+extern crate nslfmt;
+
+use nslfmt::preprocess::*;
+use std::path::Path;
+
+fn expand(src: &str) -> String {
+    let mut pp = Preprocessor::new();
+    pp.process_source(src, Path::new("test.nsl")).unwrap()
+}
+
+fn expand_err(src: &str) -> PreprocessError {
+    let mut pp = Preprocessor::new();
+    pp.process_source(src, Path::new("test.nsl")).unwrap_err()
+}
+
+#[test]
+fn object_like_macro() {
+    let out = expand("#define W 8\n#define HALF W\nwire a[W], b[HALF];\n");
+    assert_eq!(out, "wire a[8], b[8];\n");
+}
+
+#[test]
+fn defined_names() {
+    let mut pp = Preprocessor::new();
+    pp.process_source(
+        "#define B 1\n#define A\n#define C\n#undef C\n",
+        Path::new("test.nsl"),
+    )
+    .unwrap();
+    assert_eq!(pp.defined_names(), vec!["A", "B"]);
+}
+
+#[test]
+fn undef() {
+    let out = expand("#define W 8\na[W];\n#undef W\na[W];\n");
+    assert_eq!(out, "a[8];\na[W];\n");
+}
+
+#[test]
+fn self_reference() {
+    // not expanded again in its own value
+    let out = expand("#define A A + 1\nx = A;\n");
+    assert_eq!(out, "x = A + 1;\n");
+}
+
+#[test]
+fn not_in_comments_strings_or_numbers() {
+    let out = expand("#define W 8\n#define hFF 0\n// W\n/* W\nW */ W \"W\" 8'hFF\n");
+    assert_eq!(out, "// W\n/* W\nW */ 8 \"W\" 8'hFF\n");
+}
+
+#[test]
+fn ifdef_else() {
+    let src = "#define A
+#ifdef A
+a
+#ifndef A
+x
+#else
+b
+#endif
+#else
+y
+#endif
+#ifdef B
+z
+#endif
+";
+    assert_eq!(expand(src), "a\nb\n");
+}
+
+#[test]
+fn skipped_region() {
+    // directives in a skipped region are not evaluated
+    let src = "#ifdef NOT_DEFINED
+#error never
+#include \"missing.nsh\"
+#if 1
+#endif
+#define X 1
+#endif
+X
+";
+    assert_eq!(expand(src), "X\n");
+}
+
+#[test]
+fn line_continuation() {
+    let out = expand("#define PORTS input a; \\\n    input b;\nPORTS\n");
+    assert_eq!(out, "input a; input b;\n");
+}
+
+#[test]
+fn defines_from_outside() {
+    let mut pp = Preprocessor::new();
+    pp.define("SIM", "");
+    let out = pp
+        .process_source("#ifdef SIM\nsim\n#endif\n", Path::new("test.nsl"))
+        .unwrap();
+    assert_eq!(out, "sim\n");
+}
+
+#[test]
+fn include() {
+    let mut pp = Preprocessor::new();
+    pp.add_include_path("nsl_samples/preprocess/inc");
+    let out = pp
+        .process_file(Path::new("nsl_samples/preprocess/top.nsl"))
+        .unwrap();
+    assert!(out.contains("reg r[(16 * 2)] = 0;"));
+    assert!(out.contains("wire w[(16 * 2)]; // WIDTH stays in a comment"));
+    assert!(!out.contains("reg r[(16 * 2)];"));
+    // defined by the included header
+    assert!(pp.is_defined("REGS_NSH"));
+}
+
+#[test]
+fn include_not_found() {
+    let mut pp = Preprocessor::new();
+    let e = pp
+        .process_file(Path::new("nsl_samples/preprocess/top.nsl"))
+        .unwrap_err();
+    match e.kind {
+        PreprocessErrorKind::IncludeNotFound(ref name) => assert_eq!(name, "width.nsh"),
+        _ => panic!("unexpected error {:?}", e),
+    }
+    assert_eq!(e.line, 2);
+    assert_eq!(
+        format!("{}", e),
+        "nsl_samples/preprocess/top.nsl:2: cannot find the include file \"width.nsh\""
+    );
+}
+
+#[test]
+fn collect_defines() {
+    let mut pp = Preprocessor::new();
+    pp.add_include_path("nsl_samples/preprocess/inc");
+    pp.collect_defines(Path::new("nsl_samples/preprocess/top.nsl"));
+    assert_eq!(
+        pp.defined_names(),
+        vec!["FAST", "HALF", "INIT", "REGS", "REGS_NSH", "WIDTH"]
+    );
+
+    // without the include path, width.nsh is passed over
+    let mut pp = Preprocessor::new();
+    pp.collect_defines(Path::new("nsl_samples/preprocess/top.nsl"));
+    assert_eq!(pp.defined_names(), vec!["FAST", "INIT", "REGS", "REGS_NSH"]);
+}
+
+#[test]
+fn unbalanced() {
+    let e = expand_err("a\n#endif\n");
+    assert_eq!(
+        format!("{}", e),
+        "test.nsl:2: #endif without a matching #ifdef"
+    );
+
+    let e = expand_err("#ifdef A\n#else\n#else\n#endif\n");
+    assert_eq!(e.line, 3);
+
+    let e = expand_err("#ifdef A\n#ifdef B\n#endif\n");
+    assert_eq!(
+        format!("{}", e),
+        "test.nsl:1: #ifdef is not closed by #endif"
+    );
+}
+
+#[test]
+fn error_directive() {
+    let e = expand_err("#ifndef WIDTH\n#error WIDTH is required\n#endif\n");
+    assert_eq!(format!("{}", e), "test.nsl:2: #error WIDTH is required");
+}
+
+#[test]
+fn if_is_unsupported() {
+    let e = expand_err("#if W > 8\n#endif\n");
+    assert_eq!(
+        format!("{}", e),
+        "test.nsl:1: #if is not supported by the preprocessor"
+    );
+}